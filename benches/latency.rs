@@ -0,0 +1,181 @@
+//! How long requests take with lots of bots playing at once.
+//!
+//! Starts the server in a process of its own, then for each bot count in
+//! `BENCH_BOTS` (50, 150 and 300 by default) has that many bots move around
+//! for `BENCH_SECS` seconds (10 by default) while `/api/v1/spectate` is polled
+//! as fast as it'll answer. It prints latency percentiles for both and, on
+//! Linux, how much CPU time the server spent per request. Latencies depend on
+//! whether the bots leave the server a core to itself, but the CPU time
+//! doesn't. Set `BENCH_SERVER` to the `server` binary of another build to
+//! compare with it. Run it with `cargo bench --bench latency`.
+
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+use rand::seq::SliceRandom;
+use reqwest::Client;
+use serde_json::Value;
+
+const HOST: &str = "127.0.0.1:3917";
+const DIRECTIONS: [&str; 4] = ["up", "down", "left", "right"];
+/// What `/proc` counts CPU time in, which is 100 a second on every Linux
+/// worth benchmarking on.
+const CLOCK_TICK: Duration = Duration::from_millis(10);
+
+fn env_or(name: &str, default: u64) -> u64 {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let counts: Vec<usize> = std::env::var("BENCH_BOTS")
+        .unwrap_or_else(|_| "50,150,300".into())
+        .split(',')
+        .map(|count| count.trim().parse())
+        .collect::<Result<_, _>>()?;
+    let run_for = Duration::from_secs(env_or("BENCH_SECS", 10));
+    // room for every bot of every run, in case some don't manage to quit
+    let server = Server::start(counts.iter().sum())?;
+    let url = format!("http://{HOST}");
+
+    let client = Client::new();
+    for bots in counts {
+        let cpu_before = server.cpu_time();
+        let until = Instant::now() + run_for;
+        let players: Vec<_> = (0..bots)
+            .map(|_| tokio::spawn(play(client.clone(), url.clone(), until)))
+            .collect();
+        let spectating = tokio::spawn(spectate(client.clone(), url.clone(), until));
+
+        let mut moves = Vec::new();
+        for player in players {
+            moves.extend(player.await??);
+        }
+        let spectating = spectating.await??;
+        let requests = moves.len() + spectating.len();
+
+        println!("{bots} bots for {run_for:?}");
+        report("move", moves);
+        report("spectate", spectating);
+        if let (Some(before), Some(after)) = (cpu_before, server.cpu_time()) {
+            let per_request = (after - before).as_secs_f64() / requests.max(1) as f64;
+            println!("server cpu: {:.1}µs per request", per_request * 1e6);
+        }
+    }
+    Ok(())
+}
+
+/// A server running in a process of its own, so the bots can't hold it up by
+/// sharing its runtime. It's killed when dropped.
+struct Server(Child);
+
+impl Server {
+    /// Start a server with room for `bots` players on a board big enough for
+    /// them to move around on.
+    fn start(bots: usize) -> anyhow::Result<Self> {
+        let path =
+            std::env::var("BENCH_SERVER").unwrap_or_else(|_| env!("CARGO_BIN_EXE_server").into());
+        let child = Command::new(path)
+            .env("HAXXOR_HOST", HOST)
+            .env("HAXXOR_LOG_LEVEL", "warn")
+            .env("HAXXOR_WIDTH", "100")
+            .env("HAXXOR_HEIGHT", "60")
+            .env("HAXXOR_MAX_PLAYERS", bots.to_string())
+            .env("HAXXOR_ACTION_INTERVAL_MS", "20")
+            .spawn()?;
+        std::thread::sleep(Duration::from_millis(500));
+        Ok(Self(child))
+    }
+
+    /// How much CPU time the server has used so far, where `/proc` says.
+    fn cpu_time(&self) -> Option<Duration> {
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", self.0.id())).ok()?;
+        // the fields after the command name, which may have spaces in it,
+        // start with the third, so user and system time are the 12th and 13th
+        let fields: Vec<_> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+        let user: u32 = fields.get(11)?.parse().ok()?;
+        let system: u32 = fields.get(12)?.parse().ok()?;
+        Some(CLOCK_TICK * (user + system))
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Register and move in random directions until `until`, timing every move,
+/// then quit to make room for the next run.
+async fn play(client: Client, url: String, until: Instant) -> anyhow::Result<Vec<Duration>> {
+    let url = format!("{url}/api/v1");
+    let player: Value = client
+        .post(format!("{url}/players"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let id = player["id"].as_u64().unwrap_or_default();
+    let token = player["token"].as_str().unwrap_or_default().to_string();
+
+    let mut latencies = Vec::new();
+    while Instant::now() < until {
+        let dir = *DIRECTIONS.choose(&mut rand::thread_rng()).unwrap();
+        let start = Instant::now();
+        let res = client
+            .post(format!("{url}/players/{id}/move"))
+            .header("x-haxxor-token", &token)
+            .json(&serde_json::json!({ "dir": dir }))
+            .send()
+            .await?;
+        // rate limited moves still count, they're answered all the same
+        res.bytes().await?;
+        latencies.push(start.elapsed());
+        tokio::time::sleep(Duration::from_millis(25)).await;
+    }
+
+    client
+        .delete(format!("{url}/players/{id}"))
+        .header("x-haxxor-token", &token)
+        .send()
+        .await?;
+    Ok(latencies)
+}
+
+/// Fetch the board over and over until `until`, timing every fetch.
+async fn spectate(client: Client, url: String, until: Instant) -> anyhow::Result<Vec<Duration>> {
+    let mut latencies = Vec::new();
+    while Instant::now() < until {
+        let start = Instant::now();
+        client
+            .get(format!("{url}/api/v1/spectate"))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        latencies.push(start.elapsed());
+    }
+    Ok(latencies)
+}
+
+fn report(name: &str, mut latencies: Vec<Duration>) {
+    if latencies.is_empty() {
+        println!("{name}: no requests");
+        return;
+    }
+    latencies.sort();
+    let at = |p: f64| latencies[((latencies.len() - 1) as f64 * p) as usize];
+    println!(
+        "{name}: {} requests, p50 {:?}, p99 {:?}, max {:?}",
+        latencies.len(),
+        at(0.5),
+        at(0.99),
+        latencies[latencies.len() - 1],
+    );
+}
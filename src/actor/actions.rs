@@ -1,11 +1,15 @@
 use anyhow::anyhow;
 use pathfinding::prelude::astar;
+use rand::Rng;
 
-use crate::api::{ApiClient, FullResponse, MoveDir};
+use crate::api::{ApiClient, ApiError, FullResponse, GameMode, MoveDir, PlayerLocation};
 
 use super::position::Pos;
 use super::state::Game;
 
+/// How close "it" can get before a rescue mission is called off.
+const RESCUE_DANGER_DISTANCE: u16 = 2;
+
 pub enum Action {
     Look,
     Move(MoveDir),
@@ -24,39 +28,78 @@ pub async fn try_quit(client: &ApiClient, state: &mut Game) -> anyhow::Result<()
 }
 
 /// Determine the best course of action and take it.
-pub async fn take_action(client: &ApiClient, state: &mut Game) -> anyhow::Result<()> {
+pub async fn take_action(client: &mut ApiClient, state: &mut Game) -> anyhow::Result<()> {
     match determine_action(state) {
         Action::Register => {
             // println!("registering");
             let new_state = client.register().await?;
+            // without a websocket the client just keeps polling over http
+            if let Err(e) = client.connect(new_state.id).await {
+                tracing::debug!("could not open websocket: {e}");
+            }
             state.game = Some(new_state);
         }
         Action::Look => match &mut state.game {
             None => return Err(anyhow!("Cannot look before registering as a player")),
             Some(s) => {
                 // println!("looking");
-                let new_partial = client.look(s.id).await?;
-                s.inner = new_partial;
+                match client.look(s.id).await {
+                    Ok(new_partial) => s.inner = new_partial,
+                    Err(e) => return recover(state, e),
+                }
             }
         },
         Action::Move(dir) => match &mut state.game {
             None => return Err(anyhow!("Cannot move before registering as a player")),
             Some(s) => {
                 // println!("moving {dir}");
-                let new_partial = client.mv(s.id, dir.clone()).await?;
-                s.inner = new_partial;
+                match client.mv(s.id, dir.clone()).await {
+                    Ok(new_partial) => s.inner = new_partial,
+                    Err(e) => return recover(state, e),
+                }
             }
         },
     }
     Ok(())
 }
 
-fn determine_action(state: &Game) -> Action {
+/// Sit out a turn if we acted too soon, got frozen or the game is paused, and
+/// start over if the server has forgotten about us, e.g. after being reaped
+/// for idling.
+fn recover(state: &mut Game, e: ApiError) -> anyhow::Result<()> {
+    match e {
+        ApiError::RateLimited { .. } | ApiError::Frozen(_) => Ok(()),
+        ApiError::Paused(_) => {
+            // only look until the game says it's been resumed
+            if let Some(game) = &mut state.game {
+                game.inner.paused = true;
+            }
+            Ok(())
+        }
+        ApiError::UnknownPlayer(_) => {
+            state.game = None;
+            Ok(())
+        }
+        e => Err(e.into()),
+    }
+}
+
+fn determine_action(state: &mut Game) -> Action {
     match &state.game {
         None => Action::Register,
         Some(game) => {
-            let dir = if game.inner.is_it {
+            // with limited vision an empty list doesn't mean we're alone, so go
+            // looking instead of standing around
+            let dir = if game.inner.frozen || game.inner.paused {
+                // nothing to do but wait for someone to come and thaw us out,
+                // or for the game to be resumed
+                MoveDir::None
+            } else if game.vision_radius.is_some() && game.inner.players.is_empty() {
+                explore_dir(game, &mut state.explore_target)
+            } else if game.inner.is_it {
                 chase_dir(game)
+            } else if let Some(dir) = rescue_dir(game) {
+                dir
             } else {
                 flee_dir(game)
             };
@@ -82,6 +125,34 @@ fn chase_dir(game: &FullResponse) -> MoveDir {
     dir_from_path(&me, path)
 }
 
+/// In freeze tag, head for the closest frozen teammate to thaw them out,
+/// unless "it" is too close for comfort.
+fn rescue_dir(game: &FullResponse) -> Option<MoveDir> {
+    if game.mode != GameMode::Freeze {
+        return None;
+    }
+    let me = Pos(game.inner.x, game.inner.y);
+    let players = &game.inner.players;
+    let in_danger = players
+        .iter()
+        .any(|p| p.is_it && me.distance(&Pos(p.x, p.y)) <= RESCUE_DANGER_DISTANCE);
+    if in_danger {
+        return None;
+    }
+    let target = players
+        .iter()
+        .filter(|p| p.frozen && !p.is_it)
+        .map(|p| Pos(p.x, p.y))
+        .min_by_key(|p| me.distance(p))?;
+    let path = astar(
+        &me,
+        |p| p.successors(game, true),
+        |p| p.distance(&target),
+        |p| *p == target,
+    );
+    path.is_some().then(|| dir_from_path(&me, path))
+}
+
 fn flee_dir(game: &FullResponse) -> MoveDir {
     let me = Pos(game.inner.x, game.inner.y);
     let it = it_player_pos(game);
@@ -95,6 +166,42 @@ fn flee_dir(game: &FullResponse) -> MoveDir {
     dir_from_path(&me, path)
 }
 
+fn explore_dir(game: &FullResponse, target: &mut Option<(i16, i16)>) -> MoveDir {
+    let me = Pos(game.inner.x, game.inner.y);
+    let reached = target.is_none_or(|(x, y)| me == Pos(x, y) || game.occupied(x, y));
+    if reached {
+        *target = random_open_square(game);
+    }
+
+    let Some((x, y)) = *target else {
+        return MoveDir::None;
+    };
+    let goal = Pos(x, y);
+    let path = astar(
+        &me,
+        |p| p.successors(game, false),
+        |p| p.distance(&goal),
+        |p| *p == goal,
+    );
+    if path.is_none() {
+        // unreachable, try somewhere else next time
+        *target = None;
+    }
+    dir_from_path(&me, path)
+}
+
+fn random_open_square(game: &FullResponse) -> Option<(i16, i16)> {
+    let mut rng = rand::thread_rng();
+    (0..100)
+        .map(|_| {
+            (
+                rng.gen_range(0..game.map_width),
+                rng.gen_range(0..game.map_height),
+            )
+        })
+        .find(|&(x, y)| !game.occupied(x, y))
+}
+
 fn dir_from_path(me: &Pos, path: Option<(Vec<Pos>, u16)>) -> MoveDir {
     match path {
         None => MoveDir::None,
@@ -134,16 +241,24 @@ fn max_square(game: &FullResponse, it: &Pos) -> Pos {
     target
 }
 
+/// The closest it player, since in infection there can be lots of them.
 fn it_player_pos(game: &FullResponse) -> Pos {
-    match game.inner.players.iter().find(|p| p.is_it) {
-        Some(p) => Pos(p.x, p.y),
-        None => Pos(game.inner.x, game.inner.y),
-    }
+    let me = Pos(game.inner.x, game.inner.y);
+    game.inner
+        .players
+        .iter()
+        .filter(|p| p.is_it)
+        .map(|p| Pos(p.x, p.y))
+        .min_by_key(|p| me.distance(p))
+        .unwrap_or(me)
 }
 
 fn closest_player(game: &FullResponse, me: &Pos) -> Pos {
     let mut closest = None;
-    for p in &game.inner.players {
+    // no point chasing someone who can't be tagged, who's already it or who's
+    // already frozen
+    let taggable = |p: &&PlayerLocation| !p.immune && !p.is_it && !p.frozen;
+    for p in game.inner.players.iter().filter(taggable) {
         let d = me.distance(&Pos(p.x, p.y));
         match closest {
             None => {
@@ -11,7 +11,7 @@ mod state;
 pub use state::{Game, GameState};
 
 pub async fn run(state: GameState) -> anyhow::Result<()> {
-    let client = ApiClient::default();
+    let mut client = ApiClient::default();
 
     // let speed = [100u64].choose(&mut rand::thread_rng()).unwrap();
     // let speed = rand::thread_rng().gen_range(200);
@@ -19,7 +19,15 @@ pub async fn run(state: GameState) -> anyhow::Result<()> {
     let mut interval = interval(Duration::from_millis(1010u64));
 
     loop {
-        interval.tick().await;
+        tokio::select! {
+            _ = interval.tick() => {}
+            Some(update) = client.updates() => {
+                if let Some(game) = &mut state.lock().await.game {
+                    game.inner = update.inner;
+                }
+                continue;
+            }
+        }
         // random hiccups to make the game more interesting
         // if rand::thread_rng().gen_bool(1.0 / 2.0) {
         //     interval.tick().await;
@@ -31,7 +39,7 @@ pub async fn run(state: GameState) -> anyhow::Result<()> {
             return actions::try_quit(&client, &mut lock).await;
         }
 
-        let res = actions::take_action(&client, &mut lock).await;
+        let res = actions::take_action(&mut client, &mut lock).await;
         if res.is_err() || lock.should_quit {
             // println!("error {res:#?}");
             if lock.retries > 0 {
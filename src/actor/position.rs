@@ -8,24 +8,21 @@ impl Pos {
         self.0.abs_diff(other.0) + self.1.abs_diff(other.1)
     }
 
+    /// Neighboring tiles that can be moved onto. Walls and the edge of the map
+    /// are always left out, other players only if `include_occupied` is false.
     pub fn successors(&self, game: &FullResponse, include_occupied: bool) -> Vec<(Self, u16)> {
         let &Self(x, y) = self;
         let mut successors = Vec::new();
 
-        if include_occupied || !game.occupied(x + 1, y) {
-            successors.push((Self(x + 1, y), 1));
-        }
-
-        if include_occupied || !game.occupied(x - 1, y) {
-            successors.push((Self(x - 1, y), 1));
-        }
-
-        if include_occupied || !game.occupied(x, y + 1) {
-            successors.push((Self(x, y + 1), 1));
-        }
-
-        if include_occupied || !game.occupied(x, y - 1) {
-            successors.push((Self(x, y - 1), 1));
+        for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+            let open = if include_occupied {
+                !game.blocked(nx, ny)
+            } else {
+                !game.occupied(nx, ny)
+            };
+            if open {
+                successors.push((Self(nx, ny), 1));
+            }
         }
 
         successors
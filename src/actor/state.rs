@@ -12,6 +12,8 @@ pub type GameState = Arc<Mutex<Game>>;
 #[derive(Debug, Clone)]
 pub struct Game {
     pub game: Option<FullResponse>,
+    /// Where we're headed while nobody is in sight.
+    pub explore_target: Option<(i16, i16)>,
     pub retries: i16,
     pub should_quit: bool,
 }
@@ -20,6 +22,7 @@ impl Game {
     pub fn new_state() -> GameState {
         Arc::new(Mutex::new(Self {
             game: None,
+            explore_target: None,
             retries: 2,
             should_quit: false,
         }))
@@ -50,6 +53,11 @@ impl Game {
 impl Shape for Game {
     fn draw(&self, painter: &mut Painter) {
         if let Some(game) = &self.game {
+            for wall in &game.walls {
+                if let Some((x, y)) = painter.get_point(wall.x.into(), wall.y.into()) {
+                    painter.paint(x, y, Color::DarkGray);
+                }
+            }
             if let Some((x, y)) = painter.get_point(game.inner.x.into(), game.inner.y.into()) {
                 let color = if game.inner.is_it {
                     Color::Red
@@ -62,6 +70,8 @@ impl Shape for Game {
                 if let Some((x, y)) = painter.get_point(player.x.into(), player.y.into()) {
                     let color = if player.is_it {
                         Color::Red
+                    } else if player.frozen {
+                        Color::LightBlue
                     } else {
                         Color::Cyan
                     };
@@ -1,29 +1,53 @@
 use anyhow::anyhow;
 use core::fmt;
-use serde::Deserialize;
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 
 use crate::server::url;
 
+use super::error::ApiError;
 use super::json;
 
 pub struct ApiClient {
     url: String,
     client: reqwest::Client,
+    token: Option<String>,
+    socket: Option<WebSocketStream<MaybeTlsStream<TcpStream>>>,
 }
 
 impl ApiClient {
-    /// Make a call to any endpoint and parse a json response
-    pub async fn call<T: fmt::Debug + for<'de> Deserialize<'de>>(
+    /// Play in a named room on the server instead of the default one.
+    pub fn in_room(mut self, room: &str) -> Self {
+        self.url = format!("{}/api/v1/rooms/{room}", url());
+        self
+    }
+
+    /// Send a request to the server with the player's token and parse the JSON
+    /// response, or the error the server sent back instead.
+    pub async fn send<T: for<'de> Deserialize<'de>>(
         &self,
-        url: &str,
-    ) -> anyhow::Result<T> {
-        let res = self.client.get(url).send().await?;
-        let res = res.json::<T>().await;
-        // println!("{res:#?}");
-        match res {
-            Ok(r) => Ok(r),
-            Err(e) => Err(anyhow!(e)),
+        mut req: reqwest::RequestBuilder,
+    ) -> Result<T, ApiError> {
+        if let Some(token) = &self.token {
+            req = req.header("X-Haxxor-Token", token);
+        }
+        let res = req.send().await?;
+        let status = res.status();
+        if status.is_success() {
+            return Ok(res.json::<T>().await?);
+        }
+        let text = res.text().await?;
+        match serde_json::from_str::<json::ErrorResponse>(&text) {
+            Ok(err) => Err(ApiError::from_response(Some(status.as_u16()), err)),
+            Err(_) => Err(ApiError::Rejected {
+                status: Some(status.as_u16()),
+                message: text,
+            }),
         }
     }
 
@@ -37,8 +61,14 @@ impl ApiClient {
     ///
     /// To register you need to make an HTTP request to the following url:
     ///     `http://xortag.apphb.com/register`
-    pub async fn register(&self) -> anyhow::Result<json::FullResponse> {
-        self.call(&format!("{}/register", self.url)).await
+    ///
+    /// The secret token in the response is kept by the client and sent along
+    /// with every request after this one.
+    pub async fn register(&mut self) -> Result<json::FullResponse, ApiError> {
+        let req = self.client.post(format!("{}/players", self.url));
+        let res: json::FullResponse = self.send(req).await?;
+        self.token = res.token.clone();
+        Ok(res)
     }
 
     /// Once you are registered you can start moving your player around. This is the
@@ -51,8 +81,18 @@ impl ApiClient {
     /// If you move to the same space where another player is and one of you is it,
     /// that counts as a tag. If neither of you are it, you won't go anywhere. No
     /// piggybacking here.
-    pub async fn mv(&self, id: u16, dir: MoveDir) -> anyhow::Result<json::PartialResponse> {
-        self.call(&format!("{}/move{dir}/{id}", self.url)).await
+    pub async fn mv(&mut self, id: u16, dir: MoveDir) -> Result<json::PartialResponse, ApiError> {
+        if dir == MoveDir::None {
+            return self.look(id).await;
+        }
+        if self.socket.is_some() {
+            return self.command(json::Command::Move { dir }).await;
+        }
+        let req = self
+            .client
+            .post(format!("{}/players/{id}/move", self.url))
+            .json(&json::Command::Move { dir });
+        self.send(req).await
     }
 
     /// If you want to get an update on what's going on in the world, but don't want
@@ -62,30 +102,107 @@ impl ApiClient {
     ///
     /// As with moving, make sure to supply your user id. Also, in response to your
     /// request you'll receive back an updated JSON object.
-    pub async fn look(&self, id: u16) -> anyhow::Result<json::PartialResponse> {
-        self.call(&format!("{}/look/{id}", self.url)).await
+    pub async fn look(&mut self, id: u16) -> Result<json::PartialResponse, ApiError> {
+        if self.socket.is_some() {
+            return self.command(json::Command::Look).await;
+        }
+        let req = self.client.get(format!("{}/players/{id}", self.url));
+        self.send(req).await
     }
 
     /// Attempt to quit from the game. If this call succeeds, the server will remove
     /// the player and return the final state that the player would have seen.
-    pub async fn quit(&self, id: u16) -> anyhow::Result<json::PartialResponse> {
-        self.call(&format!("{}/quit/{id}", self.url)).await
+    pub async fn quit(&self, id: u16) -> Result<json::PartialResponse, ApiError> {
+        let req = self.client.delete(format!("{}/players/{id}", self.url));
+        self.send(req).await
+    }
+
+    /// Open a websocket for the player so that moves and looks go over it and
+    /// the server pushes updates whenever the board changes. If the socket
+    /// ever closes, the client falls back to plain HTTP requests.
+    pub async fn connect(&mut self, id: u16) -> Result<(), ApiError> {
+        let base = self.url.replacen("http", "ws", 1);
+        let token = self.token.as_deref().unwrap_or_default();
+        let (socket, _) = connect_async(format!("{base}/players/{id}/ws?token={token}")).await?;
+        self.socket = Some(socket);
+        Ok(())
+    }
+
+    /// Wait for the next update pushed over the websocket. Never resolves if
+    /// the client isn't connected.
+    pub async fn updates(&mut self) -> Option<json::FullResponse> {
+        loop {
+            match self.receive().await.ok()?.message {
+                json::SocketMessage::Update(res) => return Some(res),
+                json::SocketMessage::Error(_) => continue,
+            }
+        }
+    }
+
+    /// Send a command over the websocket and wait for its reply. Updates
+    /// pushed in the meantime are skipped, since the reply is newer anyway.
+    async fn command(&mut self, cmd: json::Command) -> Result<json::PartialResponse, ApiError> {
+        let text = serde_json::to_string(&cmd)?;
+        if let Err(e) = self.socket_mut()?.send(Message::Text(text)).await {
+            self.socket = None;
+            return Err(e.into());
+        }
+        loop {
+            let frame = self.receive().await?;
+            if frame.kind == json::FrameKind::Push {
+                continue;
+            }
+            return match frame.message {
+                json::SocketMessage::Update(res) => Ok(res.inner),
+                json::SocketMessage::Error(err) => Err(ApiError::from_response(None, err)),
+            };
+        }
+    }
+
+    async fn receive(&mut self) -> anyhow::Result<json::SocketFrame> {
+        let Some(socket) = self.socket.as_mut() else {
+            return std::future::pending().await;
+        };
+        while let Some(frame) = socket.next().await {
+            match frame {
+                Ok(Message::Text(text)) => return Ok(serde_json::from_str(&text)?),
+                Ok(Message::Close(_)) | Err(_) => break,
+                Ok(_) => continue,
+            }
+        }
+        self.socket = None;
+        Err(anyhow!("websocket closed"))
+    }
+
+    fn socket_mut(&mut self) -> anyhow::Result<&mut WebSocketStream<MaybeTlsStream<TcpStream>>> {
+        self.socket
+            .as_mut()
+            .ok_or(anyhow!("websocket is not connected"))
     }
 }
 
 impl Default for ApiClient {
+    /// A client for the server at `HAXXOR_URL`, playing in the room named by
+    /// `HAXXOR_ROOM` if it's set.
     fn default() -> Self {
-        Self {
-            url: url(),
+        let client = Self {
+            url: format!("{}/api/v1", url()),
             client: reqwest::Client::builder()
                 .timeout(Duration::from_millis(750))
                 .build()
                 .expect("hardcoded config for builder should not panic"),
+            token: None,
+            socket: None,
+        };
+        match std::env::var("HAXXOR_ROOM") {
+            Ok(room) => client.in_room(&room),
+            Err(_) => client,
         }
     }
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MoveDir {
     Up,
     Down,
@@ -106,6 +223,21 @@ impl From<&String> for MoveDir {
     }
 }
 
+impl FromStr for MoveDir {
+    type Err = String;
+
+    /// Unlike `From<&String>`, only accept real moves.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "up" => Ok(Self::Up),
+            "down" => Ok(Self::Down),
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            _ => Err(s.to_string()),
+        }
+    }
+}
+
 impl fmt::Display for MoveDir {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
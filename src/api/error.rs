@@ -0,0 +1,108 @@
+use std::fmt;
+use std::time::Duration;
+
+use tokio_tungstenite::tungstenite;
+
+use super::json::{ErrorCode, ErrorResponse};
+
+/// Why a call to the server failed, so bots can react to the ones that matter,
+/// e.g. re-registering after `UnknownPlayer` or backing off on `RateLimited`.
+#[derive(Debug)]
+pub enum ApiError {
+    UnknownPlayer(String),
+    Unauthorized(String),
+    RateLimited {
+        retry_after: Duration,
+    },
+    InvalidDirection(String),
+    Frozen(String),
+    Paused(String),
+    BoardFull(String),
+    UnknownRoom(String),
+    RoomClosed(String),
+    /// The server turned the request down for any other reason.
+    Rejected {
+        /// The HTTP status, or `None` if the error came over the websocket.
+        status: Option<u16>,
+        message: String,
+    },
+    /// The server couldn't be reached or sent back something unreadable.
+    Other(anyhow::Error),
+}
+
+impl ApiError {
+    /// Map the error a `/api/v1` route or websocket sent back to what went
+    /// wrong.
+    pub fn from_response(status: Option<u16>, res: ErrorResponse) -> Self {
+        let message = res.error.message;
+        match res.error.code {
+            ErrorCode::UnknownPlayer => Self::UnknownPlayer(message),
+            ErrorCode::Unauthorized => Self::Unauthorized(message),
+            ErrorCode::RateLimited => Self::RateLimited {
+                retry_after: Duration::from_millis(res.error.retry_after_ms.unwrap_or_default()),
+            },
+            ErrorCode::InvalidDirection => Self::InvalidDirection(message),
+            ErrorCode::Frozen => Self::Frozen(message),
+            ErrorCode::Paused => Self::Paused(message),
+            ErrorCode::BoardFull => Self::BoardFull(message),
+            ErrorCode::UnknownRoom => Self::UnknownRoom(message),
+            ErrorCode::RoomClosed => Self::RoomClosed(message),
+            ErrorCode::AdminOnly | ErrorCode::BadRequest | ErrorCode::Unknown => {
+                Self::Rejected { status, message }
+            }
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownPlayer(message)
+            | Self::Unauthorized(message)
+            | Self::InvalidDirection(message)
+            | Self::Frozen(message)
+            | Self::Paused(message)
+            | Self::BoardFull(message)
+            | Self::UnknownRoom(message)
+            | Self::RoomClosed(message) => write!(f, "{message}"),
+            Self::RateLimited { retry_after } => {
+                write!(f, "rate limited, try again in {retry_after:?}")
+            }
+            Self::Rejected {
+                status: Some(status),
+                message,
+            } => write!(f, "{status}: {message}"),
+            Self::Rejected {
+                status: None,
+                message,
+            } => write!(f, "{message}"),
+            Self::Other(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Other(e)
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Other(e.into())
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Other(e.into())
+    }
+}
+
+impl From<tungstenite::Error> for ApiError {
+    fn from(e: tungstenite::Error) -> Self {
+        Self::Other(e.into())
+    }
+}
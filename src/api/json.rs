@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+use super::MoveDir;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -9,14 +12,23 @@ pub struct FullResponse {
     /// the game knows it's you, rather than that shady looking guy over there
     /// in the corner.
     pub id: u16,
-    /// How many tiles wide the map is.
+
+    /// The secret token for your player, only sent when you register.
     ///
-    /// Default 30
-    pub map_height: i16,
+    /// Send it back with every other request, either in the `X-Haxxor-Token`
+    /// header or as a `token` query parameter. That's how the game knows it's
+    /// really you and not someone who guessed your id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
 
     /// How many tiles high the map is.
     ///
-    /// Default 50
+    /// Default 10, but the server can be configured otherwise.
+    pub map_height: i16,
+
+    /// How many tiles wide the map is.
+    ///
+    /// Default 25, but the server can be configured otherwise.
     pub map_width: i16,
 
     /// Your player's name.
@@ -24,17 +36,59 @@ pub struct FullResponse {
     /// Everyone's got to have a name.
     pub name: String,
 
+    /// The walls on the map, only sent when you register.
+    ///
+    /// Nobody can move onto a wall, so you'll have to go around them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub walls: Vec<Position>,
+
+    /// How far away you can see other players, if the server limits it.
+    ///
+    /// When this is set, `players` only has the players within this distance
+    /// of you, so if it's empty someone could still be out there.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vision_radius: Option<u16>,
+
+    /// How the distance for `visionRadius` is measured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vision_metric: Option<VisionMetric>,
+
+    /// The rules the game is played by.
+    ///
+    /// In classic tag tagging someone makes them it instead of you, in
+    /// infection they join you and the last one standing wins, and in freeze
+    /// tag they're stuck until a teammate rescues them.
+    #[serde(default)]
+    pub mode: GameMode,
+
+    /// Where the current round is at, if the server plays in timed rounds.
+    ///
+    /// Tags only count while the round is `playing`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<RoundPhase>,
+
+    /// How long until the round moves on to its next phase, if it's on a
+    /// timer. Nobody knows how long the lobby will take.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_remaining_ms: Option<u64>,
+
     /// Include the sub-struct that we care about during updates.
     #[serde(flatten)]
     pub inner: PartialResponse,
 }
 
 impl FullResponse {
-    pub fn occupied(&self, x: i16, y: i16) -> bool {
+    /// Whether a tile is off the map or a wall.
+    pub fn blocked(&self, x: i16, y: i16) -> bool {
         x < 0
             || y < 0
             || x >= self.map_width
             || y >= self.map_height
+            || self.walls.iter().any(|w| w.x == x && w.y == y)
+    }
+
+    pub fn occupied(&self, x: i16, y: i16) -> bool {
+        self.blocked(x, y)
             || self.inner.x == x && self.inner.y == y
             || self.inner.players.iter().any(|p| p.x == x && p.y == y)
     }
@@ -52,6 +106,19 @@ pub struct PartialResponse {
     /// False means run for your life.
     pub is_it: bool,
 
+    /// Whether you've been frozen in freeze tag.
+    ///
+    /// You can still look, but moving will fail until a teammate steps onto
+    /// you.
+    #[serde(default)]
+    pub frozen: bool,
+
+    /// Whether an admin has paused the game.
+    ///
+    /// Nobody can move while the game is paused, but you can still look.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub paused: bool,
+
     /// An array of other players that are close enough for you to see.
     ///
     /// Each player has an X position, a Y position and whether or not they are
@@ -73,9 +140,232 @@ pub struct PartialResponse {
     pub y: i16,
 }
 
+/// The ways distance can be measured when working out who a player can see.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VisionMetric {
+    /// Number of steps along the grid, `|dx| + |dy|`.
+    #[default]
+    Manhattan,
+    /// Straight line distance, `sqrt(dx^2 + dy^2)`.
+    Euclidean,
+}
+
+impl VisionMetric {
+    /// Whether something `dx` and `dy` tiles away is within `radius`.
+    pub fn within(&self, dx: i16, dy: i16, radius: u16) -> bool {
+        let (dx, dy, radius) = (
+            u32::from(dx.unsigned_abs()),
+            u32::from(dy.unsigned_abs()),
+            u32::from(radius),
+        );
+        match self {
+            Self::Manhattan => dx + dy <= radius,
+            Self::Euclidean => dx * dx + dy * dy <= radius * radius,
+        }
+    }
+}
+
+impl FromStr for VisionMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "manhattan" => Ok(Self::Manhattan),
+            "euclidean" => Ok(Self::Euclidean),
+            _ => Err(format!("unknown vision metric {s:?}")),
+        }
+    }
+}
+
+/// The rules a game can be played by.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GameMode {
+    /// One player is it, and tagging someone makes them it instead.
+    #[default]
+    Classic,
+    /// Tagged players are it for good. Once everyone is it, the last player
+    /// tagged wins and a new round starts.
+    Infection,
+    /// Tagged players are frozen in place until a teammate steps onto them.
+    /// Once every runner is frozen, a new round starts.
+    Freeze,
+}
+
+impl FromStr for GameMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "classic" => Ok(Self::Classic),
+            "infection" => Ok(Self::Infection),
+            "freeze" => Ok(Self::Freeze),
+            _ => Err(format!("unknown game mode {s:?}")),
+        }
+    }
+}
+
+/// Where a timed round is at. Rounds go from the lobby to warmup once enough
+/// players have joined, then to playing, then over, and back to warmup.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoundPhase {
+    /// Waiting for enough players to start.
+    #[default]
+    Lobby,
+    /// Get into position, tags don't count yet.
+    Warmup,
+    /// The round is on.
+    Playing,
+    /// The round is done and scored, the next one starts soon.
+    Over,
+}
+
+/// A single tile on the map.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Position {
+    pub x: i16,
+    pub y: i16,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct PlayerLocation {
     pub is_it: bool,
+    /// Whether the player can't be tagged right now, because they just
+    /// joined or just tagged someone.
+    #[serde(default)]
+    pub immune: bool,
+    /// Whether the player is frozen in freeze tag, waiting to be rescued.
+    #[serde(default)]
+    pub frozen: bool,
     pub x: i16,
     pub y: i16,
 }
+
+/// A command sent by a player connected over a websocket, for example
+/// `{"action": "move", "dir": "up"}` or `{"action": "look"}`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum Command {
+    Look,
+    Move { dir: MoveDir },
+}
+
+/// A frame sent to a player connected over a websocket, e.g.
+/// `{"type": "reply", "isIt": false, ...}`.
+///
+/// Every command gets a reply, and updates are pushed whenever anything on
+/// the board changes, so there's no need to poll by looking. A push can arrive
+/// while a command is waiting on its reply, so `type` tells them apart.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SocketFrame {
+    #[serde(rename = "type")]
+    pub kind: FrameKind,
+    #[serde(flatten)]
+    pub message: SocketMessage,
+}
+
+/// Why a websocket frame was sent.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameKind {
+    /// It answers the command the player sent last.
+    Reply,
+    /// The board changed, or the player was told to leave.
+    Push,
+}
+
+/// What a websocket frame says. Commands that fail get the same error the
+/// `/api/v1` routes send back.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SocketMessage {
+    Update(FullResponse),
+    Error(ErrorResponse),
+}
+
+/// What every `/api/v1` route sends back when something goes wrong, e.g.
+/// `{"error": {"code": "rate_limited", "message": "...", "retryAfterMs": 400}}`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ErrorResponse {
+    pub error: ErrorBody,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorBody {
+    /// What went wrong, for programs.
+    pub code: ErrorCode,
+    /// What went wrong, for people.
+    pub message: String,
+    /// How long to wait before trying again, only sent when rate limited.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+}
+
+/// The machine readable reason a request failed.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// There's no player with that id, maybe they quit or were idle too long.
+    UnknownPlayer,
+    /// The player token is missing or belongs to someone else.
+    Unauthorized,
+    /// The player acted again before `actionIntervalMs` was up.
+    RateLimited,
+    /// The move wasn't up, down, left or right.
+    InvalidDirection,
+    /// The player is frozen and can't move until a teammate rescues them.
+    Frozen,
+    /// An admin paused the game, so nobody can move until it's resumed.
+    Paused,
+    /// There's no room on the board for another player.
+    BoardFull,
+    /// There's no room with that name.
+    UnknownRoom,
+    /// The room was closed, or its game crashed.
+    RoomClosed,
+    /// Players can only see so far, so watching the whole board takes the
+    /// server's admin key.
+    AdminOnly,
+    /// Anything else wrong with the request.
+    BadRequest,
+    /// A code from a newer server that this client doesn't know about.
+    #[serde(other)]
+    Unknown,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn socket_frames_say_whether_they_are_replies() {
+        let text = r#"{"type": "push", "error": {"code": "room_closed", "message": "gone"}}"#;
+        let frame: SocketFrame = serde_json::from_str(text).unwrap();
+        assert_eq!(frame.kind, FrameKind::Push);
+        assert!(matches!(
+            frame.message,
+            SocketMessage::Error(ErrorResponse {
+                error: ErrorBody {
+                    code: ErrorCode::RoomClosed,
+                    ..
+                }
+            })
+        ));
+
+        let text = r#"{"type": "reply", "id": 1000, "mapHeight": 10, "mapWidth": 25,
+            "name": "Player 1000", "isIt": true, "players": [], "x": 3, "y": 4}"#;
+        let frame: SocketFrame = serde_json::from_str(text).unwrap();
+        assert_eq!(frame.kind, FrameKind::Reply);
+        let SocketMessage::Update(res) = &frame.message else {
+            panic!("expected an update, got {frame:?}");
+        };
+        assert_eq!((res.id, res.inner.x, res.inner.y), (1000, 3, 4));
+
+        let again: serde_json::Value = serde_json::to_value(&frame).unwrap();
+        assert_eq!(again["type"], "reply");
+        assert_eq!(again["isIt"], true);
+    }
+}
@@ -1,5 +1,10 @@
 mod client;
+mod error;
 mod json;
 
 pub use client::{ApiClient, MoveDir};
-pub use json::{FullResponse, PartialResponse, PlayerLocation};
+pub use error::ApiError;
+pub use json::{
+    ErrorBody, ErrorCode, ErrorResponse, FrameKind, FullResponse, GameMode, PartialResponse,
+    PlayerLocation, Position, RoundPhase, SocketFrame, SocketMessage, VisionMetric,
+};
@@ -1,4 +1,5 @@
 #[tokio::main]
 pub async fn main() -> anyhow::Result<()> {
-    haxxor_tag::server::serve().await
+    let config = haxxor_tag::server::ServerConfig::load()?;
+    haxxor_tag::server::serve(config).await
 }
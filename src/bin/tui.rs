@@ -1,10 +1,28 @@
 use anyhow::anyhow;
+use clap::Parser;
 use haxxor_tag::{actor, tui};
+use std::path::PathBuf;
 use tokio::join;
 
+#[derive(Debug, Parser)]
+#[command(about = "Play haxxor tag with the sample bot, or watch a recorded game")]
+struct Args {
+    /// Event log to replay instead of playing
+    #[arg(long)]
+    replay: Option<PathBuf>,
+    /// Room to replay, the first one in the log if not given
+    #[arg(long, requires = "replay")]
+    room: Option<String>,
+}
+
 #[tokio::main]
 pub async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
     tui::setup_panic_hook();
+    if let Some(path) = args.replay {
+        return tui::replay(&path, args.room.as_deref());
+    }
+
     let state = actor::Game::new_state();
     let actor = tokio::spawn(actor::run(state.clone()));
     // spawn_enemies();
@@ -0,0 +1,376 @@
+use anyhow::anyhow;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+use tracing::Level;
+
+use crate::api::{GameMode, VisionMetric};
+
+/// Settings for the server, loaded from (highest priority first) command line
+/// flags, `HAXXOR_*` environment variables, a TOML config file and defaults.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Address the server listens on.
+    pub host: String,
+    /// Most verbose tracing level that gets logged.
+    pub log_level: String,
+    /// Secret needed for the `/admin` routes, which are disabled without one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admin_key: Option<String>,
+    /// File every room is saved to, see `snapshot` for the format.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<PathBuf>,
+    /// How often rooms are saved to `snapshot`, never if zero.
+    pub autosave_secs: u64,
+    /// Whether to pick up where the last `snapshot` left off on startup.
+    pub restore: bool,
+    /// File every game event is appended to as JSON lines.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event_log: Option<PathBuf>,
+    /// Size the event log can grow to before it's rotated.
+    pub event_log_max_bytes: u64,
+    #[serde(flatten)]
+    pub game: GameConfig,
+}
+
+/// Settings for the game itself, kept by `GameState` so they survive a reset.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GameConfig {
+    /// How many tiles wide the map is, ignored when a map file is used.
+    pub width: i16,
+    /// How many tiles high the map is, ignored when a map file is used.
+    pub height: i16,
+    /// Text file with walls and spawn points, see `Map` for the format.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub map: Option<PathBuf>,
+    /// The rules the game is played by.
+    pub mode: GameMode,
+    /// Registrations are turned away once this many players are on the map.
+    pub max_players: usize,
+    /// Minimum time between two actions from the same player.
+    pub action_interval_ms: u64,
+    /// Players that haven't acted for this long are removed from the game.
+    pub idle_timeout_secs: u64,
+    /// How long a player that just tagged someone can't be tagged back.
+    pub tag_back_immunity_ms: u64,
+    /// How long a newly registered player can't be tagged.
+    pub spawn_immunity_ms: u64,
+    /// How far players can see each other, unlimited if not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vision_radius: Option<u16>,
+    /// How distance is measured for `vision_radius`.
+    pub vision_metric: VisionMetric,
+    /// When set, moves and looks are queued and resolved all at once every
+    /// this many milliseconds instead of as soon as they arrive.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tick_ms: Option<u64>,
+    /// When set, the game is played in rounds this many seconds long instead
+    /// of going on forever.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub round_secs: Option<u64>,
+    /// How long players get to settle in before each round starts.
+    pub warmup_secs: u64,
+    /// How long the results of a round stand before the next one.
+    pub intermission_secs: u64,
+    /// Rounds wait in the lobby until this many players have joined.
+    pub min_players: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1:3000".into(),
+            log_level: "debug".into(),
+            admin_key: None,
+            snapshot: None,
+            autosave_secs: 60,
+            restore: false,
+            event_log: None,
+            event_log_max_bytes: 10 * 1024 * 1024,
+            game: GameConfig::default(),
+        }
+    }
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            width: 25,
+            height: 10,
+            map: None,
+            mode: GameMode::default(),
+            max_players: 50,
+            action_interval_ms: 1000,
+            idle_timeout_secs: 5 * 60,
+            tag_back_immunity_ms: 0,
+            spawn_immunity_ms: 0,
+            vision_radius: None,
+            vision_metric: VisionMetric::default(),
+            tick_ms: None,
+            round_secs: None,
+            warmup_secs: 10,
+            intermission_secs: 10,
+            min_players: 2,
+        }
+    }
+}
+
+impl GameConfig {
+    /// A copy of these settings with some of them replaced by the fields of a
+    /// JSON object, e.g. `{"width": 50, "height": 30}`.
+    pub fn with_overrides(&self, overrides: serde_json::Value) -> anyhow::Result<Self> {
+        let serde_json::Value::Object(overrides) = overrides else {
+            return Err(anyhow!("game settings must be a JSON object"));
+        };
+        let mut config = serde_json::to_value(self)?;
+        if let serde_json::Value::Object(fields) = &mut config {
+            fields.extend(overrides);
+        }
+        Ok(serde_json::from_value(config)?)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.width < 1 || self.height < 1 {
+            return Err(anyhow!("the map needs to be at least 1x1"));
+        }
+        if self.tick_ms == Some(0) {
+            return Err(anyhow!("ticks need to be at least 1ms long"));
+        }
+        if self.round_secs == Some(0) {
+            return Err(anyhow!("rounds need to be at least 1s long"));
+        }
+        Ok(())
+    }
+
+    pub fn action_interval(&self) -> Duration {
+        Duration::from_millis(self.action_interval_ms)
+    }
+
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_timeout_secs)
+    }
+
+    pub fn tag_back_immunity(&self) -> Duration {
+        Duration::from_millis(self.tag_back_immunity_ms)
+    }
+
+    pub fn spawn_immunity(&self) -> Duration {
+        Duration::from_millis(self.spawn_immunity_ms)
+    }
+
+    pub fn tick(&self) -> Option<Duration> {
+        self.tick_ms.map(Duration::from_millis)
+    }
+
+    pub fn round(&self) -> Option<Duration> {
+        self.round_secs.map(Duration::from_secs)
+    }
+
+    pub fn warmup(&self) -> Duration {
+        Duration::from_secs(self.warmup_secs)
+    }
+
+    pub fn intermission(&self) -> Duration {
+        Duration::from_secs(self.intermission_secs)
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(about = "Run a haxxor tag server")]
+struct Args {
+    /// TOML file to read settings from
+    #[arg(long, short, env = "HAXXOR_CONFIG")]
+    config: Option<PathBuf>,
+    /// Address to listen on
+    #[arg(long, env = "HAXXOR_HOST")]
+    host: Option<String>,
+    /// Most verbose level to log (error, warn, info, debug, trace)
+    #[arg(long, env = "HAXXOR_LOG_LEVEL")]
+    log_level: Option<String>,
+    /// Secret needed for the admin routes, which are disabled without one
+    #[arg(long, env = "HAXXOR_ADMIN_KEY")]
+    admin_key: Option<String>,
+    /// File to save every room to
+    #[arg(long, env = "HAXXOR_SNAPSHOT")]
+    snapshot: Option<PathBuf>,
+    /// Seconds between saves to the snapshot file, 0 to only save on request
+    #[arg(long, env = "HAXXOR_AUTOSAVE_SECS")]
+    autosave_secs: Option<u64>,
+    /// Load the snapshot file on startup if there is one
+    #[arg(long, env = "HAXXOR_RESTORE")]
+    restore: bool,
+    /// File to append game events to as JSON lines
+    #[arg(long, env = "HAXXOR_EVENT_LOG")]
+    event_log: Option<PathBuf>,
+    /// Bytes the event log can grow to before it's rotated
+    #[arg(long, env = "HAXXOR_EVENT_LOG_MAX_BYTES")]
+    event_log_max_bytes: Option<u64>,
+    /// How many tiles wide the map is
+    #[arg(long, env = "HAXXOR_WIDTH")]
+    width: Option<i16>,
+    /// How many tiles high the map is
+    #[arg(long, env = "HAXXOR_HEIGHT")]
+    height: Option<i16>,
+    /// Text file with walls and spawn points to use as the map
+    #[arg(long, env = "HAXXOR_MAP")]
+    map: Option<PathBuf>,
+    /// Rules to play by (classic, infection, freeze)
+    #[arg(long, env = "HAXXOR_MODE")]
+    mode: Option<GameMode>,
+    /// Most players allowed on the map at once
+    #[arg(long, env = "HAXXOR_MAX_PLAYERS")]
+    max_players: Option<usize>,
+    /// Minimum milliseconds between two actions from the same player
+    #[arg(long, env = "HAXXOR_ACTION_INTERVAL_MS")]
+    action_interval_ms: Option<u64>,
+    /// Seconds without an action before a player is removed
+    #[arg(long, env = "HAXXOR_IDLE_TIMEOUT_SECS")]
+    idle_timeout_secs: Option<u64>,
+    /// Milliseconds a player that just tagged someone can't be tagged back
+    #[arg(long, env = "HAXXOR_TAG_BACK_IMMUNITY_MS")]
+    tag_back_immunity_ms: Option<u64>,
+    /// Milliseconds a newly registered player can't be tagged
+    #[arg(long, env = "HAXXOR_SPAWN_IMMUNITY_MS")]
+    spawn_immunity_ms: Option<u64>,
+    /// How many tiles away players can see each other
+    #[arg(long, env = "HAXXOR_VISION_RADIUS")]
+    vision_radius: Option<u16>,
+    /// How distance is measured for the vision radius (manhattan, euclidean)
+    #[arg(long, env = "HAXXOR_VISION_METRIC")]
+    vision_metric: Option<VisionMetric>,
+    /// Resolve all moves at once every this many milliseconds
+    #[arg(long, env = "HAXXOR_TICK_MS")]
+    tick_ms: Option<u64>,
+    /// Play in timed rounds this many seconds long
+    #[arg(long, env = "HAXXOR_ROUND_SECS")]
+    round_secs: Option<u64>,
+    /// Seconds of warmup before each round
+    #[arg(long, env = "HAXXOR_WARMUP_SECS")]
+    warmup_secs: Option<u64>,
+    /// Seconds between the end of a round and the next warmup
+    #[arg(long, env = "HAXXOR_INTERMISSION_SECS")]
+    intermission_secs: Option<u64>,
+    /// Players needed before a round starts
+    #[arg(long, env = "HAXXOR_MIN_PLAYERS")]
+    min_players: Option<usize>,
+}
+
+impl ServerConfig {
+    /// Build the config from the command line, environment and config file.
+    pub fn load() -> anyhow::Result<Self> {
+        let args = Args::parse();
+        let mut config = match &args.config {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow!("could not read config {}: {e}", path.display()))?;
+                toml::from_str(&text)?
+            }
+            None => Self::default(),
+        };
+
+        if let Some(host) = args.host {
+            config.host = host;
+        }
+        if let Some(log_level) = args.log_level {
+            config.log_level = log_level;
+        }
+        if let Some(admin_key) = args.admin_key {
+            config.admin_key = Some(admin_key);
+        }
+        if let Some(snapshot) = args.snapshot {
+            config.snapshot = Some(snapshot);
+        }
+        if let Some(autosave_secs) = args.autosave_secs {
+            config.autosave_secs = autosave_secs;
+        }
+        if args.restore {
+            config.restore = true;
+        }
+        if let Some(event_log) = args.event_log {
+            config.event_log = Some(event_log);
+        }
+        if let Some(event_log_max_bytes) = args.event_log_max_bytes {
+            config.event_log_max_bytes = event_log_max_bytes;
+        }
+        let game = &mut config.game;
+        if let Some(width) = args.width {
+            game.width = width;
+        }
+        if let Some(height) = args.height {
+            game.height = height;
+        }
+        if let Some(map) = args.map {
+            game.map = Some(map);
+        }
+        if let Some(mode) = args.mode {
+            game.mode = mode;
+        }
+        if let Some(max_players) = args.max_players {
+            game.max_players = max_players;
+        }
+        if let Some(action_interval_ms) = args.action_interval_ms {
+            game.action_interval_ms = action_interval_ms;
+        }
+        if let Some(idle_timeout_secs) = args.idle_timeout_secs {
+            game.idle_timeout_secs = idle_timeout_secs;
+        }
+        if let Some(tag_back_immunity_ms) = args.tag_back_immunity_ms {
+            game.tag_back_immunity_ms = tag_back_immunity_ms;
+        }
+        if let Some(spawn_immunity_ms) = args.spawn_immunity_ms {
+            game.spawn_immunity_ms = spawn_immunity_ms;
+        }
+        if let Some(vision_radius) = args.vision_radius {
+            game.vision_radius = Some(vision_radius);
+        }
+        if let Some(vision_metric) = args.vision_metric {
+            game.vision_metric = vision_metric;
+        }
+        if let Some(tick_ms) = args.tick_ms {
+            game.tick_ms = Some(tick_ms);
+        }
+        if let Some(round_secs) = args.round_secs {
+            game.round_secs = Some(round_secs);
+        }
+        if let Some(warmup_secs) = args.warmup_secs {
+            game.warmup_secs = warmup_secs;
+        }
+        if let Some(intermission_secs) = args.intermission_secs {
+            game.intermission_secs = intermission_secs;
+        }
+        if let Some(min_players) = args.min_players {
+            game.min_players = min_players;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// How often to autosave, if there's anywhere to save to.
+    pub fn autosave(&self) -> Option<Duration> {
+        match self.autosave_secs {
+            0 => None,
+            secs => self.snapshot.as_ref().map(|_| Duration::from_secs(secs)),
+        }
+    }
+
+    pub fn log_level(&self) -> anyhow::Result<Level> {
+        self.log_level
+            .parse()
+            .map_err(|_| anyhow!("invalid log level {:?}", self.log_level))
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        self.log_level()?;
+        if self.admin_key.as_deref() == Some("") {
+            return Err(anyhow!("the admin key can't be empty"));
+        }
+        if self.restore && self.snapshot.is_none() {
+            return Err(anyhow!("restoring needs a snapshot file to restore from"));
+        }
+        self.game.validate()
+    }
+}
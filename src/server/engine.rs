@@ -0,0 +1,228 @@
+use std::future::pending;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, watch, Notify};
+use tokio::time::{interval, sleep_until, Interval};
+
+use super::error::GameError;
+use super::metrics;
+use super::state::{GameState, Sight};
+
+/// How many commands can be waiting on a room before senders wait too.
+const QUEUE_SIZE: usize = 1024;
+/// However busy a room is, its view is published at most this often.
+const PUBLISH_EVERY: Duration = Duration::from_millis(50);
+/// How often players that have gone quiet are looked for.
+const REAP_EVERY: Duration = Duration::from_secs(1);
+/// How often a room playing in timed rounds checks whether to move on.
+const ROUND_CHECK_EVERY: Duration = Duration::from_millis(250);
+
+type Command = Box<dyn FnOnce(&mut GameState) + Send>;
+
+/// A handle to a room's game. The game itself is owned by an engine task that
+/// runs the commands sent to it one at a time, so nothing ever waits on a
+/// lock, and publishes a read-only view of the game for anything that only
+/// wants to look. The engine stops once the room is closed or every handle is
+/// dropped.
+#[derive(Clone, Debug)]
+pub struct Room {
+    commands: mpsc::Sender<(Instant, Command)>,
+    view: watch::Receiver<Arc<View>>,
+    closed: Arc<Notify>,
+}
+
+/// The game as it was when it was last published, ready to be sent out so
+/// reading it costs the engine nothing.
+#[derive(Debug)]
+pub struct View {
+    /// The board's version, which only changes when the board does.
+    pub board: u64,
+    /// The game as spectators get to see it, as JSON.
+    pub spectate: String,
+    /// Everything about the game, players' tokens included, as JSON for
+    /// admins.
+    pub stats: String,
+    /// What each player could see, for players connected over a websocket.
+    pub sight: Sight,
+    pub players: usize,
+    /// Whether players can only see so far, in which case only admins get to
+    /// see the whole board.
+    pub hidden: bool,
+    pub width: i16,
+    pub height: i16,
+}
+
+impl Room {
+    /// Hand the game over to a new engine task for the room called `name`.
+    pub fn spawn(name: &str, state: GameState) -> Self {
+        let (commands, receiver) = mpsc::channel(QUEUE_SIZE);
+        let (views, view) = watch::channel(Arc::new(View::of(&state)));
+        let closed = Arc::new(Notify::new());
+        tokio::spawn(run(name.into(), state, receiver, views, closed.clone()));
+        Self {
+            commands,
+            view,
+            closed,
+        }
+    }
+
+    /// Stop the engine, even with players still connected, and wait for it
+    /// to finish. Their websockets and event streams end, and every command
+    /// still waiting on the game gets `RoomClosed`.
+    pub async fn close(&self) {
+        self.closed.notify_one();
+        let mut view = self.view.clone();
+        while view.changed().await.is_ok() {}
+    }
+
+    /// Run `command` on the game and get back what it returned.
+    pub async fn run<T: Send + 'static>(
+        &self,
+        command: impl FnOnce(&mut GameState) -> T + Send + 'static,
+    ) -> Result<T, GameError> {
+        let (reply, result) = oneshot::channel();
+        let command: Command = Box::new(move |state| {
+            // whoever sent the command may have given up waiting, which is fine
+            let _ = reply.send(command(state));
+        });
+        self.commands
+            .send((Instant::now(), command))
+            .await
+            .map_err(|_| GameError::RoomClosed)?;
+        result.await.map_err(|_| GameError::RoomClosed)
+    }
+
+    /// The most recently published view of the game.
+    pub fn view(&self) -> Arc<View> {
+        self.view.borrow().clone()
+    }
+
+    /// Get told whenever a new view is published. The sender is dropped when
+    /// the room closes.
+    pub fn watch(&self) -> watch::Receiver<Arc<View>> {
+        self.view.clone()
+    }
+}
+
+impl View {
+    /// Whether the board or who's on it has changed since the view was
+    /// published. Nothing else is worth publishing a new view for.
+    fn is_behind(&self, state: &GameState) -> bool {
+        self.board != state.board_version() || self.players != state.player_count()
+    }
+
+    fn of(state: &GameState) -> Self {
+        // SAFETY: the game is plain data that always serializes
+        Self {
+            board: state.board_version(),
+            spectate: serde_json::to_string(&state.spectate()).unwrap(),
+            stats: serde_json::to_string(state).unwrap(),
+            sight: state.sight(),
+            players: state.player_count(),
+            hidden: state.config().vision_radius.is_some(),
+            width: state.config().width,
+            height: state.config().height,
+        }
+    }
+}
+
+/// Own the game, running commands as they arrive along with the room's own
+/// timers, until the room is closed or every handle to it is gone.
+async fn run(
+    name: String,
+    mut state: GameState,
+    mut commands: mpsc::Receiver<(Instant, Command)>,
+    views: watch::Sender<Arc<View>>,
+    closed: Arc<Notify>,
+) {
+    let mut ticks = state.config().tick().map(interval);
+    let mut rounds = state.config().round().map(|_| interval(ROUND_CHECK_EVERY));
+    let mut reaper = interval(REAP_EVERY);
+    let mut publish_at = None;
+    let mut published = tokio::time::Instant::now();
+
+    loop {
+        tokio::select! {
+            command = commands.recv() => {
+                let Some((sent, command)) = command else {
+                    break;
+                };
+                metrics::record_command_wait(sent.elapsed());
+                // a command that panics takes the engine down with it, which
+                // closes the room rather than playing on with a broken game
+                command(&mut state);
+            }
+            _ = closed.notified() => {
+                state.close();
+                break;
+            }
+            _ = every(&mut ticks) => state.resolve_tick(),
+            _ = every(&mut rounds) => state.advance_round(),
+            _ = reaper.tick() => {
+                let timeout = state.config().idle_timeout();
+                for id in state.remove_idle() {
+                    tracing::info!("removed player {id} from {name} after {timeout:?} of inactivity");
+                }
+            }
+            _ = sleep_until(publish_at.unwrap_or(published)), if publish_at.is_some() => {
+                views.send_replace(Arc::new(View::of(&state)));
+                published = tokio::time::Instant::now();
+                publish_at = None;
+                continue;
+            }
+        }
+        // if the board changed, publish a new view soon, but no sooner than
+        // `PUBLISH_EVERY` after the last one
+        if publish_at.is_none() && views.borrow().is_behind(&state) {
+            publish_at = Some(published + PUBLISH_EVERY);
+        }
+    }
+    tracing::debug!("engine stopped for room {name}");
+}
+
+async fn every(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn closing_a_room_stops_it_for_every_handle() {
+        let room = Room::spawn("test", GameState::default());
+        let player = room.clone();
+        let mut views = player.watch();
+        room.close().await;
+
+        let registered = player.run(|state| state.register().is_ok()).await;
+        assert!(matches!(registered, Err(GameError::RoomClosed)));
+        assert!(views.changed().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn views_are_only_published_when_the_board_changes() {
+        let room = Room::spawn("test", GameState::default());
+        let mut views = room.watch();
+        let wait = Duration::from_millis(500);
+
+        let id = room
+            .run(|state| state.register().unwrap().id)
+            .await
+            .unwrap();
+        let published = tokio::time::timeout(wait, views.changed()).await;
+        assert!(published.is_ok(), "registering should publish a view");
+        assert_eq!(views.borrow_and_update().players, 1);
+
+        room.run(move |state| state.respond_to_player(id).unwrap())
+            .await
+            .unwrap();
+        let published = tokio::time::timeout(wait, views.changed()).await;
+        assert!(published.is_err(), "looking shouldn't publish a view");
+    }
+}
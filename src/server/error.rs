@@ -0,0 +1,129 @@
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use std::fmt;
+use std::time::Duration;
+
+use crate::api::{ErrorBody, ErrorCode, ErrorResponse};
+
+/// Everything that can go wrong with a request that a client might want to
+/// handle, sent back from the `/api/v1` routes as a JSON error with a code.
+#[derive(Debug)]
+pub enum GameError {
+    UnknownPlayer(u16),
+    Unauthorized,
+    RateLimited(Duration),
+    InvalidDirection(String),
+    Frozen(u16),
+    Paused,
+    BoardFull,
+    UnknownRoom(String),
+    RoomClosed,
+    AdminOnly,
+    BadRequest(String),
+}
+
+impl GameError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::UnknownPlayer(_) => ErrorCode::UnknownPlayer,
+            Self::Unauthorized => ErrorCode::Unauthorized,
+            Self::RateLimited(_) => ErrorCode::RateLimited,
+            Self::InvalidDirection(_) => ErrorCode::InvalidDirection,
+            Self::Frozen(_) => ErrorCode::Frozen,
+            Self::Paused => ErrorCode::Paused,
+            Self::BoardFull => ErrorCode::BoardFull,
+            Self::UnknownRoom(_) => ErrorCode::UnknownRoom,
+            Self::RoomClosed => ErrorCode::RoomClosed,
+            Self::AdminOnly => ErrorCode::AdminOnly,
+            Self::BadRequest(_) => ErrorCode::BadRequest,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::UnknownPlayer(_) | Self::UnknownRoom(_) => StatusCode::NOT_FOUND,
+            Self::Unauthorized | Self::AdminOnly => StatusCode::UNAUTHORIZED,
+            Self::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::InvalidDirection(_) | Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Frozen(_) | Self::Paused => StatusCode::CONFLICT,
+            Self::BoardFull => StatusCode::SERVICE_UNAVAILABLE,
+            Self::RoomClosed => StatusCode::GONE,
+        }
+    }
+
+    /// The JSON error sent back from the `/api/v1` routes and over websockets.
+    pub fn body(&self) -> ErrorResponse {
+        ErrorResponse {
+            error: ErrorBody {
+                code: self.code(),
+                message: self.to_string(),
+                retry_after_ms: match self {
+                    Self::RateLimited(wait) => Some(wait.as_millis() as u64),
+                    _ => None,
+                },
+            },
+        }
+    }
+
+    /// The plain text response the original routes have always sent.
+    pub fn into_legacy_response(self) -> Response {
+        let status = match self {
+            Self::UnknownPlayer(_) | Self::InvalidDirection(_) | Self::Frozen(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            Self::RateLimited(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => self.status(),
+        };
+        (status, self.retry_after(), self.to_string()).into_response()
+    }
+
+    fn retry_after(&self) -> Option<[(header::HeaderName, String); 1]> {
+        let Self::RateLimited(wait) = self else {
+            return None;
+        };
+        // Retry-After only supports whole seconds, so round up to avoid an early retry
+        let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        Some([(header::RETRY_AFTER, secs.to_string())])
+    }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownPlayer(id) => write!(f, "could not find player with id {id}"),
+            Self::Unauthorized => write!(f, "missing or invalid player token"),
+            Self::RateLimited(_) => write!(f, "too many actions, slow down"),
+            Self::InvalidDirection(dir) => {
+                write!(f, "{dir:?} is not a direction, use up, down, left or right")
+            }
+            Self::Frozen(id) => write!(f, "player {id} is frozen until a teammate rescues them"),
+            Self::Paused => write!(f, "the game is paused, hang tight"),
+            Self::BoardFull => write!(f, "the game is full, try again later"),
+            Self::UnknownRoom(name) => write!(f, "could not find room {name}"),
+            Self::RoomClosed => write!(f, "the room was closed"),
+            Self::AdminOnly => write!(
+                f,
+                "players can only see so far, so watching the whole board needs the admin key"
+            ),
+            Self::BadRequest(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+impl From<anyhow::Error> for GameError {
+    /// Keep the error if it was a `GameError` to begin with, anything else is
+    /// the client's fault.
+    fn from(e: anyhow::Error) -> Self {
+        e.downcast()
+            .unwrap_or_else(|e| Self::BadRequest(e.to_string()))
+    }
+}
+
+impl IntoResponse for GameError {
+    fn into_response(self) -> Response {
+        (self.status(), self.retry_after(), Json(self.body())).into_response()
+    }
+}
@@ -0,0 +1,355 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+use crate::api::{GameMode, MoveDir, Position};
+
+use super::rooms::DEFAULT_ROOM;
+
+/// How many rotated event logs are kept around, as `events.jsonl.1` (the
+/// newest) through `events.jsonl.5`.
+const KEEP_ROTATED: usize = 5;
+
+/// Something that happened in a game.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum GameEvent {
+    /// The room started logging, with the board and anyone already on it.
+    Open {
+        width: i16,
+        height: i16,
+        walls: Vec<Position>,
+        players: Vec<PlayerSpot>,
+        #[serde(default)]
+        mode: GameMode,
+    },
+    Register {
+        id: u16,
+        name: String,
+        x: i16,
+        y: i16,
+        is_it: bool,
+    },
+    /// A player tried to move, ending up at `x` and `y`, which is where they
+    /// started if something was in the way.
+    Move {
+        id: u16,
+        dir: MoveDir,
+        x: i16,
+        y: i16,
+    },
+    Look {
+        id: u16,
+    },
+    /// `tagger` tagged `tagged`, which in freeze tag froze them.
+    Tag {
+        tagger: u16,
+        tagged: u16,
+    },
+    /// In freeze tag, `rescuer` stepped onto `rescued` and thawed them out.
+    Unfreeze {
+        rescuer: u16,
+        rescued: u16,
+    },
+    /// A player left, and if they were it, who's it now.
+    Quit {
+        id: u16,
+        reason: QuitReason,
+        new_it: Option<u16>,
+    },
+    /// An admin moved a player straight to `x` and `y`.
+    Teleport {
+        id: u16,
+        x: i16,
+        y: i16,
+    },
+    /// An admin made a player it.
+    MadeIt {
+        id: u16,
+    },
+    Paused,
+    Resumed,
+    /// A timed round started its warmup with `it` as it.
+    RoundStart {
+        round: u32,
+        it: Option<u16>,
+    },
+    /// A round was won by `winner`, unless they left first or it was called
+    /// off for want of players, and `new_it` starts the next round as it
+    /// unless it waits for its warmup.
+    RoundOver {
+        winner: Option<u16>,
+        new_it: Option<u16>,
+    },
+    Reset,
+    /// The room was closed and nothing more will happen in it.
+    Close,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuitReason {
+    Quit,
+    Idle,
+    Kicked,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PlayerSpot {
+    pub id: u16,
+    pub name: String,
+    pub x: i16,
+    pub y: i16,
+    pub is_it: bool,
+    #[serde(default)]
+    pub frozen: bool,
+}
+
+/// The board at one point in a logged game, rebuilt by playing its events.
+#[derive(Clone, Debug, Default)]
+pub struct Board {
+    pub width: i16,
+    pub height: i16,
+    pub walls: Vec<Position>,
+    pub players: Vec<PlayerSpot>,
+    pub mode: GameMode,
+}
+
+/// One line of the event log.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EventRecord {
+    /// Milliseconds since the Unix epoch.
+    pub time_ms: u64,
+    pub room: String,
+    #[serde(flatten)]
+    pub event: GameEvent,
+}
+
+/// Where a room sends its events, cheap to clone so every room can have one.
+#[derive(Clone, Debug)]
+pub struct EventLog {
+    room: String,
+    sender: mpsc::UnboundedSender<EventRecord>,
+}
+
+impl EventLog {
+    /// Start writing events to `path` as JSON lines, moving the file aside
+    /// once it grows past `max_bytes`.
+    pub async fn open(path: PathBuf, max_bytes: u64) -> anyhow::Result<Self> {
+        let file = append(&path).await?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(write_events(receiver, file, path, max_bytes));
+        Ok(Self {
+            room: String::new(),
+            sender,
+        })
+    }
+
+    /// The same log, with events marked as coming from `room`.
+    pub fn for_room(&self, room: &str) -> Self {
+        Self {
+            room: room.into(),
+            sender: self.sender.clone(),
+        }
+    }
+
+    pub fn record(&self, event: GameEvent) {
+        let time_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        // the writer only stops if the server is shutting down
+        let _ = self.sender.send(EventRecord {
+            time_ms,
+            room: self.room.clone(),
+            event,
+        });
+    }
+}
+
+impl Board {
+    /// Play one event onto the board.
+    pub fn apply(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::Open {
+                width,
+                height,
+                walls,
+                players,
+                mode,
+            } => {
+                self.width = *width;
+                self.height = *height;
+                self.walls = walls.clone();
+                self.players = players.clone();
+                self.mode = *mode;
+            }
+            GameEvent::Register {
+                id,
+                name,
+                x,
+                y,
+                is_it,
+            } => self.players.push(PlayerSpot {
+                id: *id,
+                name: name.clone(),
+                x: *x,
+                y: *y,
+                is_it: *is_it,
+                frozen: false,
+            }),
+            GameEvent::Move { id, x, y, .. } => {
+                if let Some(p) = self.player(*id) {
+                    (p.x, p.y) = (*x, *y);
+                }
+            }
+            GameEvent::Teleport { id, x, y } => {
+                if let Some(p) = self.player(*id) {
+                    (p.x, p.y) = (*x, *y);
+                }
+            }
+            GameEvent::MadeIt { id } => {
+                let mode = self.mode;
+                for p in &mut self.players {
+                    if p.id == *id {
+                        p.is_it = true;
+                        p.frozen = false;
+                    } else if mode != GameMode::Infection {
+                        p.is_it = false;
+                    }
+                }
+            }
+            GameEvent::Look { .. } | GameEvent::Paused | GameEvent::Resumed | GameEvent::Close => {}
+            GameEvent::Tag { tagger, tagged } => {
+                let mode = self.mode;
+                if mode == GameMode::Classic {
+                    if let Some(p) = self.player(*tagger) {
+                        p.is_it = false;
+                    }
+                }
+                if let Some(p) = self.player(*tagged) {
+                    match mode {
+                        GameMode::Freeze => p.frozen = true,
+                        _ => p.is_it = true,
+                    }
+                }
+            }
+            GameEvent::Unfreeze { rescued, .. } => {
+                if let Some(p) = self.player(*rescued) {
+                    p.frozen = false;
+                }
+            }
+            GameEvent::Quit { id, new_it, .. } => {
+                self.players.retain(|p| p.id != *id);
+                if let Some(p) = new_it.and_then(|id| self.player(id)) {
+                    p.is_it = true;
+                    p.frozen = false;
+                }
+            }
+            GameEvent::RoundStart { it: new_it, .. } | GameEvent::RoundOver { new_it, .. } => {
+                for p in &mut self.players {
+                    p.is_it = Some(p.id) == *new_it;
+                    p.frozen = false;
+                }
+            }
+            GameEvent::Reset => self.players.clear(),
+        }
+    }
+
+    /// The `Open` event that starts a log with the board as it is now.
+    pub fn open(&self) -> GameEvent {
+        GameEvent::Open {
+            width: self.width,
+            height: self.height,
+            walls: self.walls.clone(),
+            players: self.players.clone(),
+            mode: self.mode,
+        }
+    }
+
+    fn player(&mut self, id: u16) -> Option<&mut PlayerSpot> {
+        self.players.iter_mut().find(|p| p.id == id)
+    }
+}
+
+async fn append(path: &Path) -> anyhow::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(|e| anyhow!("could not open event log {}: {e}", path.display()))
+}
+
+async fn write_events(
+    mut receiver: mpsc::UnboundedReceiver<EventRecord>,
+    mut file: File,
+    path: PathBuf,
+    max_bytes: u64,
+) {
+    let mut size = file.metadata().await.map_or(0, |m| m.len());
+    // every open room's board, so each new log can start with them
+    let mut boards: BTreeMap<String, Board> = BTreeMap::new();
+    while let Some(record) = receiver.recv().await {
+        match &record.event {
+            GameEvent::Close => {
+                boards.remove(&record.room);
+            }
+            event => boards.entry(record.room.clone()).or_default().apply(event),
+        }
+        size += write(&mut file, &record, &path).await;
+
+        if size >= max_bytes {
+            match rotate(&path).await {
+                Ok(new_file) => {
+                    file = new_file;
+                    size = 0;
+                    // the default room goes first, as it does in a fresh log
+                    let mut rooms: Vec<_> = boards.iter().collect();
+                    rooms.sort_by_key(|(room, _)| *room != DEFAULT_ROOM);
+                    for (room, board) in rooms {
+                        let header = EventRecord {
+                            time_ms: record.time_ms,
+                            room: room.clone(),
+                            event: board.open(),
+                        };
+                        size += write(&mut file, &header, &path).await;
+                    }
+                }
+                Err(e) => tracing::error!("{e}"),
+            }
+        }
+    }
+}
+
+/// Write one record as a line of JSON, returning how many bytes it took.
+async fn write(file: &mut File, record: &EventRecord, path: &Path) -> u64 {
+    let Ok(mut line) = serde_json::to_vec(record) else {
+        return 0;
+    };
+    line.push(b'\n');
+    match file.write_all(&line).await {
+        Ok(()) => line.len() as u64,
+        Err(e) => {
+            tracing::error!("could not write to event log {}: {e}", path.display());
+            0
+        }
+    }
+}
+
+/// Shift every old log up by one, dropping the oldest, and start a new one.
+async fn rotate(path: &Path) -> anyhow::Result<File> {
+    let rotated = |n: usize| PathBuf::from(format!("{}.{n}", path.display()));
+    for n in (1..KEEP_ROTATED).rev() {
+        // older logs may not exist yet
+        let _ = tokio::fs::rename(rotated(n), rotated(n + 1)).await;
+    }
+    tokio::fs::rename(path, rotated(1))
+        .await
+        .map_err(|e| anyhow!("could not rotate event log {}: {e}", path.display()))?;
+    append(path).await
+}
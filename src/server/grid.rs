@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+/// Who is on each tile of the board and where each player is in the game's
+/// list of players, so finding a player by id or by position doesn't mean
+/// going through every player. `GameState` keeps it up to date whenever
+/// players join, move or leave.
+#[derive(Debug, Default)]
+pub struct Grid {
+    width: i16,
+    height: i16,
+    /// The id of the player on each tile, row by row.
+    tiles: Vec<Option<u16>>,
+    /// Each player's index in the game's list of players.
+    index: HashMap<u16, usize>,
+}
+
+impl Grid {
+    pub fn new(width: i16, height: i16) -> Self {
+        Self {
+            width,
+            height,
+            tiles: vec![None; width as usize * height as usize],
+            index: HashMap::new(),
+        }
+    }
+
+    /// The player on a tile, if there is one.
+    pub fn at(&self, x: i16, y: i16) -> Option<u16> {
+        self.tile(x, y).and_then(|tile| self.tiles[tile])
+    }
+
+    /// Where the player is in the game's list of players.
+    pub fn index_of(&self, id: u16) -> Option<usize> {
+        self.index.get(&id).copied()
+    }
+
+    /// Every player's index in the game's list of players.
+    pub fn indexes(&self) -> HashMap<u16, usize> {
+        self.index.clone()
+    }
+
+    /// Put a player that was just added to the end of the list on the board.
+    pub fn add(&mut self, id: u16, (x, y): (i16, i16)) {
+        self.index.insert(id, self.index.len());
+        self.set(x, y, Some(id));
+    }
+
+    /// Move a player from one tile to another. Everyone in a tick moves at
+    /// once, so someone may already have moved onto `from` when this is
+    /// called, in which case it's theirs now and is left alone.
+    pub fn moved(&mut self, id: u16, from: (i16, i16), to: (i16, i16)) {
+        if self.at(from.0, from.1) == Some(id) {
+            self.set(from.0, from.1, None);
+        }
+        self.set(to.0, to.1, Some(id));
+    }
+
+    /// Take a player off the board. `after` is everyone that came after them
+    /// in the list, who all move up one now that they're gone.
+    pub fn remove(&mut self, id: u16, (x, y): (i16, i16), after: impl Iterator<Item = u16>) {
+        if let Some(idx) = self.index.remove(&id) {
+            for (i, other) in after.enumerate() {
+                self.index.insert(other, idx + i);
+            }
+        }
+        if self.at(x, y) == Some(id) {
+            self.set(x, y, None);
+        }
+    }
+
+    /// Every empty tile that isn't `blocked`, row by row.
+    pub fn empty(&self, blocked: impl Fn(i16, i16) -> bool) -> Vec<(i16, i16)> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| self.at(x, y).is_none() && !blocked(x, y))
+            .collect()
+    }
+
+    fn set(&mut self, x: i16, y: i16, id: Option<u16>) {
+        if let Some(tile) = self.tile(x, y) {
+            self.tiles[tile] = id;
+        }
+    }
+
+    fn tile(&self, x: i16, y: i16) -> Option<usize> {
+        let on_board = (0..self.width).contains(&x) && (0..self.height).contains(&y);
+        on_board.then(|| y as usize * self.width as usize + x as usize)
+    }
+}
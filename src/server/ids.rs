@@ -0,0 +1,161 @@
+use rand::Rng;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+/// Every id a player can get. Starting at 1000 keeps ids at least four digits
+/// long, and leaves room for tens of thousands of players at once, so most ids
+/// handed out are five digits.
+const IDS: RangeInclusive<u16> = 1000..=u16::MAX;
+
+/// How long after a player leaves before their id can be handed out again, so
+/// a bot that missed being kicked can't mistake someone new for itself.
+const REUSE_AFTER: Duration = Duration::from_secs(300);
+
+/// Hands out player ids that are never shared by two players. Ids are given
+/// out in order from a random starting point, wrapping around at the end, so
+/// an id only comes up again after every other id has had its turn, and never
+/// within `reuse_after` of being released.
+#[derive(Debug)]
+pub struct IdAllocator {
+    /// The next id to try.
+    next: u16,
+    in_use: HashSet<u16>,
+    /// When each recently released id was released.
+    retired: HashMap<u16, Instant>,
+    /// The same ids in the order they were released, to forget them by.
+    retired_order: VecDeque<(u16, Instant)>,
+    reuse_after: Duration,
+}
+
+impl Default for IdAllocator {
+    fn default() -> Self {
+        Self::new(REUSE_AFTER)
+    }
+}
+
+impl IdAllocator {
+    pub fn new(reuse_after: Duration) -> Self {
+        Self {
+            next: rand::thread_rng().gen_range(IDS),
+            in_use: HashSet::new(),
+            retired: HashMap::new(),
+            retired_order: VecDeque::new(),
+            reuse_after,
+        }
+    }
+
+    /// A new id nobody else has, or `None` if every id is taken or was
+    /// released too recently.
+    pub fn allocate(&mut self) -> Option<u16> {
+        self.forget_retired();
+        for _ in IDS {
+            let id = self.next;
+            self.next = if id == *IDS.end() {
+                *IDS.start()
+            } else {
+                id + 1
+            };
+            if !self.in_use.contains(&id) && !self.retired.contains_key(&id) {
+                self.in_use.insert(id);
+                return Some(id);
+            }
+        }
+        None
+    }
+
+    /// Mark an id as taken, e.g. by a player restored from a snapshot.
+    /// Returns whether it was free.
+    pub fn claim(&mut self, id: u16) -> bool {
+        self.retired.remove(&id);
+        self.in_use.insert(id)
+    }
+
+    /// Give back the id of a player that left. It won't be handed out again
+    /// until `reuse_after` has passed.
+    pub fn release(&mut self, id: u16) {
+        if self.in_use.remove(&id) {
+            let now = Instant::now();
+            self.retired.insert(id, now);
+            self.retired_order.push_back((id, now));
+        }
+    }
+
+    fn forget_retired(&mut self) {
+        while let Some(&(id, at)) = self.retired_order.front() {
+            if at.elapsed() < self.reuse_after {
+                break;
+            }
+            self.retired_order.pop_front();
+            // the id may have been claimed and released again since
+            if self.retired.get(&id) == Some(&at) {
+                self.retired.remove(&id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_unique() {
+        let mut ids = IdAllocator::default();
+        let mut seen = HashSet::new();
+        for _ in 0..5000 {
+            let id = ids.allocate().unwrap();
+            assert!(IDS.contains(&id));
+            assert!(seen.insert(id), "{id} was handed out twice");
+        }
+    }
+
+    #[test]
+    fn every_id_can_be_in_use_at_once() {
+        let mut ids = IdAllocator::default();
+        let count = IDS.len();
+        let all: HashSet<_> = (0..count).map(|_| ids.allocate().unwrap()).collect();
+        assert_eq!(all.len(), count);
+        assert_eq!(ids.allocate(), None);
+    }
+
+    #[test]
+    fn released_ids_are_not_reused_straight_away() {
+        let mut ids = IdAllocator::default();
+        let count = IDS.len();
+        let all: Vec<_> = (0..count).map(|_| ids.allocate().unwrap()).collect();
+        ids.release(all[7]);
+        // even with nothing else free, the released id has to wait
+        assert_eq!(ids.allocate(), None);
+    }
+
+    #[test]
+    fn released_ids_come_back_after_every_other_id() {
+        let mut ids = IdAllocator::new(Duration::ZERO);
+        let first = ids.allocate().unwrap();
+        ids.release(first);
+        for _ in 1..IDS.len() {
+            assert_ne!(ids.allocate(), Some(first));
+        }
+        assert_eq!(ids.allocate(), Some(first));
+    }
+
+    #[test]
+    fn released_ids_are_reused_once_their_time_is_up() {
+        let mut ids = IdAllocator::new(Duration::ZERO);
+        let all: Vec<_> = (0..IDS.len()).map(|_| ids.allocate().unwrap()).collect();
+        ids.release(all[7]);
+        assert_eq!(ids.allocate(), Some(all[7]));
+    }
+
+    #[test]
+    fn claimed_ids_are_never_handed_out() {
+        let mut ids = IdAllocator::default();
+        assert!(ids.claim(1234));
+        assert!(!ids.claim(1234));
+        for _ in 1..IDS.len() {
+            assert_ne!(ids.allocate(), Some(1234));
+        }
+        assert_eq!(ids.allocate(), None);
+    }
+}
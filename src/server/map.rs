@@ -0,0 +1,92 @@
+use anyhow::anyhow;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::api::Position;
+
+/// The static layout of the board: its size, walls and spawn points.
+///
+/// Maps can be loaded from plain text files where every line is a row of the
+/// board, starting with `y = 0` at the top:
+///
+/// - `#` is a wall that nobody can move onto
+/// - `.` is open floor
+/// - `S` is open floor where new players may be placed when they register
+///
+/// If a map has no spawn points, players can start on any open floor.
+#[derive(Clone, Debug, Serialize)]
+pub struct Map {
+    #[serde(skip)]
+    pub width: i16,
+    #[serde(skip)]
+    pub height: i16,
+    pub walls: Vec<Position>,
+    #[serde(skip)]
+    pub spawns: Vec<(i16, i16)>,
+    #[serde(skip)]
+    blocked: HashSet<(i16, i16)>,
+}
+
+impl Map {
+    /// An empty rectangle without any walls.
+    pub fn open(width: i16, height: i16) -> Self {
+        Self {
+            width,
+            height,
+            walls: Vec::new(),
+            spawns: Vec::new(),
+            blocked: HashSet::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("could not read map {}: {e}", path.display()))?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let rows: Vec<&str> = text.trim_end().lines().map(str::trim_end).collect();
+        let width = rows.first().map_or(0, |r| r.chars().count());
+        if width == 0 {
+            return Err(anyhow!("map is empty"));
+        }
+        if width > i16::MAX as usize || rows.len() > i16::MAX as usize {
+            return Err(anyhow!("map is too big"));
+        }
+
+        let mut map = Self::open(width as i16, rows.len() as i16);
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(anyhow!("row {y} of the map is not {width} tiles wide"));
+            }
+            for (x, tile) in row.chars().enumerate() {
+                let pos = (x as i16, y as i16);
+                match tile {
+                    '#' => {
+                        map.walls.push(Position { x: pos.0, y: pos.1 });
+                        map.blocked.insert(pos);
+                    }
+                    'S' => map.spawns.push(pos),
+                    '.' => {}
+                    c => return Err(anyhow!("unknown tile {c:?} at ({x}, {y}) in map")),
+                }
+            }
+        }
+
+        if map.floor_tiles() == 0 {
+            return Err(anyhow!("map has no open floor"));
+        }
+        Ok(map)
+    }
+
+    /// Whether a tile is off the board or a wall.
+    pub fn blocked(&self, x: i16, y: i16) -> bool {
+        x < 0 || y < 0 || x >= self.width || y >= self.height || self.blocked.contains(&(x, y))
+    }
+
+    pub fn floor_tiles(&self) -> usize {
+        self.width as usize * self.height as usize - self.blocked.len()
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+use super::rooms::Rooms;
+
+/// Upper bounds of the histogram buckets, in seconds.
+const BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+/// Everything the server counts for `/metrics`, apart from what's read
+/// straight from the rooms when the metrics are rendered.
+#[derive(Debug, Default)]
+struct Metrics {
+    /// Requests by route and status code.
+    requests: Mutex<BTreeMap<(String, u16), u64>>,
+    latency: Mutex<BTreeMap<String, Histogram>>,
+    rate_limited: AtomicU64,
+    command_wait: Mutex<Histogram>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Histogram {
+    /// How many observations fell in each bucket, not counting lower ones.
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if let Some(i) = BUCKETS.iter().position(|&le| secs <= le) {
+            self.buckets[i] += 1;
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let (sep, plain) = match labels {
+            "" => ("", String::new()),
+            labels => (",", format!("{{{labels}}}")),
+        };
+        let mut total = 0;
+        for (le, count) in BUCKETS.iter().zip(self.buckets) {
+            total += count;
+            let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"{le}\"}} {total}");
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {}",
+            self.count
+        );
+        let _ = writeln!(out, "{name}_sum{plain} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{plain} {}", self.count);
+    }
+}
+
+/// Count a finished HTTP request against the route it matched.
+pub fn record_request(route: &str, status: u16, elapsed: Duration) {
+    *METRICS
+        .requests
+        .lock()
+        .unwrap()
+        .entry((route.into(), status))
+        .or_default() += 1;
+    METRICS
+        .latency
+        .lock()
+        .unwrap()
+        .entry(route.into())
+        .or_default()
+        .observe(elapsed);
+}
+
+pub fn record_rate_limited() {
+    METRICS.rate_limited.fetch_add(1, Ordering::Relaxed);
+}
+
+/// How long a command waited for its room's engine to get to it.
+pub fn record_command_wait(elapsed: Duration) {
+    METRICS.command_wait.lock().unwrap().observe(elapsed);
+}
+
+/// All metrics in the Prometheus text exposition format.
+pub async fn render(rooms: &Rooms) -> String {
+    let mut out = String::new();
+
+    out.push_str("# HELP haxxor_http_requests_total HTTP requests by route and status.\n");
+    out.push_str("# TYPE haxxor_http_requests_total counter\n");
+    for ((route, status), count) in METRICS.requests.lock().unwrap().iter() {
+        let route = escape(route);
+        let _ = writeln!(
+            out,
+            "haxxor_http_requests_total{{route=\"{route}\",status=\"{status}\"}} {count}"
+        );
+    }
+
+    out.push_str("# HELP haxxor_http_request_duration_seconds Time to respond by route.\n");
+    out.push_str("# TYPE haxxor_http_request_duration_seconds histogram\n");
+    for (route, histogram) in METRICS.latency.lock().unwrap().iter() {
+        let labels = format!("route=\"{}\"", escape(route));
+        histogram.render(&mut out, "haxxor_http_request_duration_seconds", &labels);
+    }
+
+    out.push_str("# HELP haxxor_rate_limited_total Actions turned away for coming too fast.\n");
+    out.push_str("# TYPE haxxor_rate_limited_total counter\n");
+    let rate_limited = METRICS.rate_limited.load(Ordering::Relaxed);
+    let _ = writeln!(out, "haxxor_rate_limited_total {rate_limited}");
+
+    // copied out so the std mutex isn't held while waiting on the rooms below
+    let command_wait = METRICS.command_wait.lock().unwrap().clone();
+    out.push_str(
+        "# HELP haxxor_room_command_wait_seconds Time commands spent queued for a room.\n",
+    );
+    out.push_str("# TYPE haxxor_room_command_wait_seconds histogram\n");
+    command_wait.render(&mut out, "haxxor_room_command_wait_seconds", "");
+
+    let mut players = String::new();
+    let mut tags = String::new();
+    let mut tags_per_minute = String::new();
+    for (name, room) in rooms.all() {
+        let counts = room.run(|state| (state.tags(), state.tags_last_minute()));
+        // a room closed in the meantime has nothing left to report
+        let Ok((total, last_minute)) = counts.await else {
+            continue;
+        };
+        let name = escape(&name);
+        let _ = writeln!(
+            players,
+            "haxxor_players{{room=\"{name}\"}} {}",
+            room.view().players
+        );
+        let _ = writeln!(tags, "haxxor_tags_total{{room=\"{name}\"}} {total}");
+        let _ = writeln!(
+            tags_per_minute,
+            "haxxor_tags_per_minute{{room=\"{name}\"}} {last_minute}"
+        );
+    }
+    out.push_str("# HELP haxxor_players Players in each room.\n");
+    out.push_str("# TYPE haxxor_players gauge\n");
+    out.push_str(&players);
+    out.push_str("# HELP haxxor_tags_total Tags in each room since it was last reset.\n");
+    out.push_str("# TYPE haxxor_tags_total counter\n");
+    out.push_str(&tags);
+    out.push_str("# HELP haxxor_tags_per_minute Tags in each room over the last minute.\n");
+    out.push_str("# TYPE haxxor_tags_per_minute gauge\n");
+    out.push_str(&tags_per_minute);
+
+    out
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', r"\\").replace('"', r#"\""#)
+}
@@ -1,27 +1,57 @@
 use anyhow::anyhow;
 use tokio::net::TcpListener;
 
+mod config;
+mod engine;
+mod error;
+mod events;
+mod grid;
+mod ids;
+mod map;
+mod metrics;
+mod rooms;
+mod round;
 mod routes;
+mod score;
+mod snapshot;
+mod socket;
+mod sse;
 mod state;
+mod tick;
 
-use state::GameState;
+pub use config::{GameConfig, ServerConfig};
+use events::EventLog;
+pub use events::{Board, EventRecord, GameEvent, PlayerSpot};
+use rooms::Rooms;
 
 pub fn url() -> String {
     std::env::var("HAXXOR_URL").unwrap_or("http://127.0.0.1:3000".into())
 }
 
-fn host() -> String {
-    std::env::var("HAXXOR_HOST").unwrap_or("127.0.0.1:3000".into())
-}
-
-pub async fn serve() -> anyhow::Result<()> {
+pub async fn serve(config: ServerConfig) -> anyhow::Result<()> {
     tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::DEBUG)
+        .with_max_level(config.log_level()?)
         .init();
 
-    let state = GameState::new_server_state();
+    let events = match &config.event_log {
+        Some(path) => Some(EventLog::open(path.clone(), config.event_log_max_bytes).await?),
+        None => None,
+    };
+    let state = Rooms::new_server_state(&config, events)?;
+    if let (true, Some(path)) = (config.restore, &config.snapshot) {
+        match snapshot::load(path)? {
+            Some(rooms) => {
+                tracing::info!("restoring {} rooms from {}", rooms.len(), path.display());
+                state.restore(rooms)?;
+            }
+            None => tracing::warn!("no snapshot at {} yet, starting fresh", path.display()),
+        }
+    }
+    if let (Some(every), Some(path)) = (config.autosave(), &config.snapshot) {
+        tokio::spawn(snapshot::autosave(state.clone(), path.clone(), every));
+    }
     let router = routes::build_router(state);
-    let host = host();
+    let host = config.host;
 
     match TcpListener::bind(&host).await {
         Ok(listener) => {
@@ -0,0 +1,158 @@
+use anyhow::anyhow;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use super::config::{GameConfig, ServerConfig};
+use super::engine::Room;
+use super::events::EventLog;
+use super::state::GameState;
+
+/// The room that the original, room-less routes play in.
+pub const DEFAULT_ROOM: &str = "default";
+
+pub type ServerState = Arc<Rooms>;
+
+/// Every game being played on the server, each in its own named room.
+#[derive(Debug)]
+pub struct Rooms {
+    rooms: RwLock<HashMap<String, Room>>,
+    /// Settings new rooms start from unless they override them.
+    config: GameConfig,
+    /// Where rooms are saved to, if anywhere.
+    snapshot: Option<PathBuf>,
+    /// Where every room's events go, if anywhere.
+    events: Option<EventLog>,
+    /// Needed for the admin routes, which are disabled without one.
+    admin_key: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoomSummary {
+    name: String,
+    players: usize,
+    width: i16,
+    height: i16,
+}
+
+impl Rooms {
+    pub fn new_server_state(
+        config: &ServerConfig,
+        events: Option<EventLog>,
+    ) -> anyhow::Result<ServerState> {
+        let rooms = Self {
+            rooms: RwLock::default(),
+            config: config.game.clone(),
+            snapshot: config.snapshot.clone(),
+            events,
+            admin_key: config.admin_key.clone(),
+        };
+        rooms.create(DEFAULT_ROOM, None)?;
+        Ok(Arc::new(rooms))
+    }
+
+    pub fn config(&self) -> &GameConfig {
+        &self.config
+    }
+
+    pub fn snapshot(&self) -> Option<&Path> {
+        self.snapshot.as_deref()
+    }
+
+    pub fn admin_key(&self) -> Option<&str> {
+        self.admin_key.as_deref()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Room> {
+        self.rooms.read().unwrap().get(name).cloned()
+    }
+
+    /// All rooms along with their names, sorted by name.
+    pub fn all(&self) -> Vec<(String, Room)> {
+        let mut rooms: Vec<_> = self
+            .rooms
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, room)| (name.clone(), room.clone()))
+            .collect();
+        rooms.sort_by(|a, b| a.0.cmp(&b.0));
+        rooms
+    }
+
+    pub fn summaries(&self) -> Vec<RoomSummary> {
+        self.all()
+            .into_iter()
+            .map(|(name, room)| {
+                let view = room.view();
+                RoomSummary {
+                    name,
+                    players: view.players,
+                    width: view.width,
+                    height: view.height,
+                }
+            })
+            .collect()
+    }
+
+    /// Open a new room, using the server's settings unless others are given.
+    pub fn create(&self, name: &str, config: Option<GameConfig>) -> anyhow::Result<Room> {
+        validate_name(name)?;
+        let state = GameState::new(config.unwrap_or_else(|| self.config.clone()))?;
+        let mut rooms = self.rooms.write().unwrap();
+        if rooms.contains_key(name) {
+            return Err(anyhow!("room {name} already exists"));
+        }
+        Ok(self.open(&mut rooms, name, state))
+    }
+
+    /// Replace every room with ones restored from a snapshot. The default room
+    /// is kept if the snapshot doesn't have one.
+    pub fn restore(&self, saved: BTreeMap<String, GameState>) -> anyhow::Result<()> {
+        for name in saved.keys() {
+            validate_name(name)?;
+        }
+        let mut rooms = self.rooms.write().unwrap();
+        rooms.retain(|name, _| name == DEFAULT_ROOM && !saved.contains_key(name));
+        for (name, state) in saved {
+            self.open(&mut rooms, &name, state);
+        }
+        Ok(())
+    }
+
+    /// Close a room and take it off the server, kicking out anyone still in
+    /// it.
+    pub async fn remove(&self, name: &str) -> anyhow::Result<()> {
+        if name == DEFAULT_ROOM {
+            return Err(anyhow!("the default room can't be removed"));
+        }
+        let room = self.rooms.write().unwrap().remove(name);
+        let Some(room) = room else {
+            return Err(anyhow!("could not find room {name}"));
+        };
+        room.close().await;
+        Ok(())
+    }
+
+    fn open(&self, rooms: &mut HashMap<String, Room>, name: &str, mut state: GameState) -> Room {
+        if let Some(events) = &self.events {
+            state.log_events(events.for_room(name));
+        }
+        let room = Room::spawn(name, state);
+        rooms.insert(name.into(), room.clone());
+        room
+    }
+}
+
+fn validate_name(name: &str) -> anyhow::Result<()> {
+    let valid_chars = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if name.is_empty() || name.len() > 32 || !valid_chars {
+        return Err(anyhow!(
+            "room names must be 1 to 32 letters, numbers, dashes or underscores"
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::api::RoundPhase;
+
+use super::config::GameConfig;
+use super::score::ScoreCard;
+
+/// How many finished rounds each room remembers.
+pub const KEEP_HISTORY: usize = 50;
+
+/// Where a room's current round is at.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Round {
+    /// Counts up with every round started.
+    pub number: u32,
+    pub phase: RoundPhase,
+    /// When the phase is up, unless it's waiting on players instead.
+    pub until: Option<Instant>,
+}
+
+/// How a round turned out.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RoundResult {
+    pub round: u32,
+    /// Milliseconds since the Unix epoch.
+    pub ended_ms: u64,
+    pub winner: Option<u16>,
+    /// Everyone's score for just this round, best first.
+    pub standings: Vec<ScoreCard>,
+}
+
+impl Round {
+    /// Timed rounds start counting when the first one starts, otherwise the
+    /// game is already in its first round.
+    pub fn new(config: &GameConfig) -> Self {
+        Self {
+            number: u32::from(config.round().is_none()),
+            ..Self::default()
+        }
+    }
+
+    pub fn time_remaining(&self) -> Option<Duration> {
+        self.until
+            .map(|until| until.saturating_duration_since(Instant::now()))
+    }
+}
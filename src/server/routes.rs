@@ -1,30 +1,94 @@
+use axum::async_trait;
 use axum::body::Bytes;
-use axum::extract::{Path, State};
-use axum::http::{HeaderMap, Request, Response, StatusCode};
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{FromRequestParts, MatchedPath, Path, Query, State};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, Request, Response, StatusCode};
+use axum::middleware::{self, Next};
 use axum::response::{Html, IntoResponse};
-use axum::routing::get;
+use axum::routing::{delete, get, post};
 use axum::{Json, Router};
-use std::time::Duration;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
+use tokio::sync::oneshot;
 use tower::ServiceBuilder;
 use tower_http::classify::ServerErrorsFailureClass;
 use tower_http::trace::TraceLayer;
 use tracing::Span;
 
-use crate::api::MoveDir;
+use crate::api::{FullResponse, MoveDir};
 
-use super::state::{GameState, ServerState};
+use super::engine::{Room, View};
+use super::error::GameError;
+use super::metrics;
+use super::rooms::{Rooms, ServerState, DEFAULT_ROOM};
+use super::score::SortBy;
+use super::snapshot;
+use super::socket::ws;
+use super::sse::events;
+use super::state::{GameState, Submitted};
 
 const HTML_PAGE: &str = include_str!("haxxor-tag.html");
+const TOKEN_HEADER: &str = "x-haxxor-token";
+const ADMIN_KEY_HEADER: &str = "x-haxxor-admin-key";
 
 pub fn build_router(state: ServerState) -> Router {
-    Router::new()
-        .route("/", get(web_page))
+    // every room gets the same routes, and the original routes without a room
+    // prefix play in the default room
+    let game = Router::new()
         .route("/register", get(register))
         .route("/look/:pid", get(look))
         .route("/move:dir/:pid", get(movement))
         .route("/quit/:pid", get(quit))
+        .route("/ws/:pid", get(ws))
+        .route("/spectate", get(spectate))
+        .route("/events", get(events))
+        .route("/leaderboard", get(leaderboard))
+        .route("/rounds", get(rounds));
+
+    // the same game with proper methods and JSON errors
+    let v1_game = Router::new()
+        .route("/players", post(v1_register))
+        .route("/players/:pid", get(v1_look).delete(v1_quit))
+        .route("/players/:pid/move", post(v1_movement))
+        .route("/players/:pid/ws", get(ws))
+        .route("/spectate", get(spectate))
+        .route("/events", get(events))
+        .route("/leaderboard", get(leaderboard))
+        .route("/rounds", get(rounds));
+    let v1 = Router::new()
+        .route("/rooms", get(list_rooms))
+        .nest("/rooms/:room", v1_game.clone())
+        .merge(v1_game);
+
+    let admin_game = Router::new()
         .route("/stats", get(stats))
-        .route("/reset", get(reset))
+        .route("/reset", post(reset))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/players", get(list_players))
+        .route("/players/:pid", delete(kick))
+        .route("/players/:pid/teleport", post(teleport))
+        .route("/players/:pid/it", post(make_it));
+    let admin = Router::new()
+        .route("/snapshot", get(get_snapshot).post(save_snapshot))
+        .route("/rooms/:room", post(create_room).delete(delete_room))
+        .nest("/rooms/:room", admin_game.clone())
+        .merge(admin_game)
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_admin));
+
+    Router::new()
+        .route("/", get(web_page))
+        .route("/metrics", get(render_metrics))
+        .route("/rooms", get(list_rooms))
+        .nest("/rooms/:room", game.clone())
+        .merge(game)
+        .nest("/api/v1", v1)
+        .nest("/admin", admin)
+        .route_layer(middleware::from_fn(track_requests))
         .with_state(state)
         .with_middleware()
 }
@@ -61,61 +125,482 @@ impl WithMiddleware for Router {
     }
 }
 
+/// The player token sent with a request, taken from the `X-Haxxor-Token` header
+/// or, failing that, the `token` query parameter.
+#[derive(Clone)]
+pub struct Token(Option<String>);
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Token {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let header = parts
+            .headers
+            .get(TOKEN_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(String::from);
+        let query = Query::<TokenQuery>::try_from_uri(&parts.uri)
+            .ok()
+            .and_then(|Query(q)| q.token);
+        Ok(Self(header.or(query)))
+    }
+}
+
+/// The admin key sent with a request, as a bearer token or in an
+/// `X-Haxxor-Admin-Key` header or, failing those, the `admin_key` query
+/// parameter for browsers that can't set headers.
+pub struct AdminKey(Option<String>);
+
+#[derive(Deserialize)]
+struct AdminKeyQuery {
+    admin_key: Option<String>,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AdminKey {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let headers = &parts.headers;
+        let bearer = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        let header = headers
+            .get(ADMIN_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .or(bearer)
+            .map(String::from);
+        let query = Query::<AdminKeyQuery>::try_from_uri(&parts.uri)
+            .ok()
+            .and_then(|Query(q)| q.admin_key);
+        Ok(Self(header.or(query)))
+    }
+}
+
+impl AdminKey {
+    /// Whether this is the server's admin key, which is never true if the
+    /// server doesn't have one.
+    pub fn is_valid(&self, rooms: &Rooms) -> bool {
+        let (Some(key), Some(admin_key)) = (&self.0, rooms.admin_key()) else {
+            return false;
+        };
+        // compared in constant time so the key can't be guessed a byte at a time
+        key.as_bytes().ct_eq(admin_key.as_bytes()).into()
+    }
+
+    /// Spectators see the whole board, so when players can't, only admins
+    /// get to spectate.
+    pub fn can_spectate(&self, rooms: &Rooms, view: &View) -> Result<(), GameError> {
+        if view.hidden && !self.is_valid(rooms) {
+            return Err(GameError::AdminOnly);
+        }
+        Ok(())
+    }
+}
+
+/// The room a request is for, from the `room` path parameter if there is one.
+pub struct GameRoom(pub Room);
+
+#[derive(Deserialize)]
+pub struct PlayerPath {
+    pub pid: u16,
+}
+
+#[derive(Deserialize)]
+pub struct MovePath {
+    pub dir: String,
+    pub pid: u16,
+}
+
+/// The body of a move, e.g. `{"dir": "up"}`. The websocket's
+/// `{"action": "move", "dir": "up"}` works too.
+#[derive(Deserialize)]
+pub struct MoveBody {
+    pub dir: String,
+}
+
+/// Where to put a player, e.g. `{"x": 3, "y": 4}`.
+#[derive(Deserialize)]
+pub struct TeleportBody {
+    pub x: i16,
+    pub y: i16,
+}
+
+#[derive(Deserialize)]
+pub struct RoomPath {
+    pub room: String,
+}
+
+#[async_trait]
+impl FromRequestParts<ServerState> for GameRoom {
+    type Rejection = GameError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &ServerState,
+    ) -> Result<Self, Self::Rejection> {
+        let params = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .map(|Path(params)| params)
+            .unwrap_or_default();
+        let name = params.get("room").map_or(DEFAULT_ROOM, String::as_str);
+        match state.get(name) {
+            Some(room) => Ok(Self(room)),
+            None => Err(GameError::UnknownRoom(name.to_string())),
+        }
+    }
+}
+
+/// Count requests and how long they took by the route they matched.
+async fn track_requests(
+    path: MatchedPath,
+    req: axum::extract::Request,
+    next: Next,
+) -> axum::response::Response {
+    let start = Instant::now();
+    let response = next.run(req).await;
+    metrics::record_request(path.as_str(), response.status().as_u16(), start.elapsed());
+    response
+}
+
+/// Only let requests through that have the server's admin key, see
+/// `AdminKey` for where it's looked for.
+async fn require_admin(
+    State(rooms): State<ServerState>,
+    key: AdminKey,
+    req: axum::extract::Request,
+    next: Next,
+) -> axum::response::Response {
+    if rooms.admin_key().is_none() {
+        return (
+            StatusCode::FORBIDDEN,
+            "admin routes are disabled until the server has an admin key",
+        )
+            .into_response();
+    }
+    if !key.is_valid(&rooms) {
+        return (StatusCode::UNAUTHORIZED, "missing or invalid admin key").into_response();
+    }
+    next.run(req).await
+}
+
+pub fn authorize(state: &GameState, pid: u16, token: &Token) -> Result<(), GameError> {
+    if state.authorize(pid, token.0.as_deref())? {
+        Ok(())
+    } else {
+        Err(GameError::Unauthorized)
+    }
+}
+
 pub async fn web_page() -> impl IntoResponse {
     Html(HTML_PAGE).into_response()
 }
 
-pub async fn register(State(data): State<ServerState>) -> impl IntoResponse {
-    let mut state = data.lock().await;
-    state.record_request();
-    let new_player = state.gen_player();
-    match state.respond_to_player(new_player.id) {
+/// Reply with the JSON body, or the error the way the original routes do.
+fn legacy<T: serde::Serialize>(res: Result<T, GameError>) -> axum::response::Response {
+    match res {
         Ok(res) => Json(res).into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        Err(e) => e.into_legacy_response(),
     }
 }
 
-pub async fn look(State(data): State<ServerState>, Path(pid): Path<u16>) -> impl IntoResponse {
-    let mut state = data.lock().await;
-    state.record_request();
-    match state.respond_to_player(pid) {
-        Ok(res) => Json(res).into_response(),
-        Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
-    }
+pub async fn register(GameRoom(room): GameRoom) -> impl IntoResponse {
+    legacy(join(&room).await)
 }
 
+pub async fn look(
+    GameRoom(room): GameRoom,
+    Path(PlayerPath { pid }): Path<PlayerPath>,
+    token: Token,
+) -> impl IntoResponse {
+    legacy(play_turn(&room, pid, MoveDir::None, token).await)
+}
+
+/// Unknown directions are treated as a look, like they always have been.
 pub async fn movement(
-    State(data): State<ServerState>,
-    Path((dir, pid)): Path<(String, u16)>,
+    GameRoom(room): GameRoom,
+    Path(MovePath { dir, pid }): Path<MovePath>,
+    token: Token,
 ) -> impl IntoResponse {
-    let mut state = data.lock().await;
-    state.record_request();
-    if state.move_player(pid, &MoveDir::from(&dir)).is_err() {
-        return (StatusCode::BAD_REQUEST).into_response();
+    legacy(play_turn(&room, pid, MoveDir::from(&dir), token).await)
+}
+
+pub async fn quit(
+    GameRoom(room): GameRoom,
+    Path(PlayerPath { pid }): Path<PlayerPath>,
+    token: Token,
+) -> impl IntoResponse {
+    legacy(leave(&room, pid, token).await)
+}
+
+pub async fn v1_register(GameRoom(room): GameRoom) -> Result<Json<FullResponse>, GameError> {
+    join(&room).await.map(Json)
+}
+
+pub async fn v1_look(
+    GameRoom(room): GameRoom,
+    Path(PlayerPath { pid }): Path<PlayerPath>,
+    token: Token,
+) -> Result<Json<FullResponse>, GameError> {
+    play_turn(&room, pid, MoveDir::None, token).await.map(Json)
+}
+
+pub async fn v1_movement(
+    GameRoom(room): GameRoom,
+    Path(PlayerPath { pid }): Path<PlayerPath>,
+    token: Token,
+    body: Result<Json<MoveBody>, JsonRejection>,
+) -> Result<Json<FullResponse>, GameError> {
+    let Json(MoveBody { dir }) = body.map_err(|e| GameError::BadRequest(e.body_text()))?;
+    let dir = dir.parse().map_err(GameError::InvalidDirection)?;
+    play_turn(&room, pid, dir, token).await.map(Json)
+}
+
+pub async fn v1_quit(
+    GameRoom(room): GameRoom,
+    Path(PlayerPath { pid }): Path<PlayerPath>,
+    token: Token,
+) -> Result<Json<FullResponse>, GameError> {
+    leave(&room, pid, token).await.map(Json)
+}
+
+async fn join(room: &Room) -> Result<FullResponse, GameError> {
+    room.run(|state| {
+        state.record_request();
+        state.register()
+    })
+    .await?
+    .map_err(GameError::from)
+}
+
+fn rate_limited(wait: Duration) -> GameError {
+    metrics::record_rate_limited();
+    GameError::RateLimited(wait)
+}
+
+/// How far a player's turn got in the engine.
+enum Turn {
+    Taken(FullResponse),
+    /// Waiting for the next tick.
+    Queued(oneshot::Receiver<Result<(), GameError>>),
+}
+
+/// Take a move or look for the player and respond with the board afterwards,
+/// waiting for the next tick first if the room resolves moves in ticks.
+pub async fn play_turn(
+    room: &Room,
+    pid: u16,
+    dir: MoveDir,
+    token: Token,
+) -> Result<FullResponse, GameError> {
+    let turn = room
+        .run(move |state| {
+            state.record_request();
+            authorize(state, pid, &token)?;
+            if let Some(wait) = state.throttle(pid) {
+                return Err(rate_limited(wait));
+            }
+            match state.submit(pid, dir)? {
+                // answer right away rather than going back to the engine
+                Submitted::Done => Ok(Turn::Taken(state.respond_to_player(pid)?)),
+                Submitted::Queued(tick) => Ok(Turn::Queued(tick)),
+                Submitted::AlreadyQueued => {
+                    Err(rate_limited(state.config().tick().unwrap_or_default()))
+                }
+            }
+        })
+        .await??;
+
+    let tick = match turn {
+        Turn::Taken(res) => return Ok(res),
+        Turn::Queued(tick) => tick,
     };
+    // the sender is only dropped without sending if the room closed
+    tick.await.map_err(|_| GameError::RoomClosed)??;
+    Ok(room
+        .run(move |state| state.respond_to_player(pid))
+        .await??)
+}
 
-    match state.respond_to_player(pid) {
-        Ok(res) => Json(res).into_response(),
+async fn leave(room: &Room, pid: u16, token: Token) -> Result<FullResponse, GameError> {
+    room.run(move |state| {
+        state.record_request();
+        authorize(state, pid, &token)?;
+        Ok(state.remove_player(pid)?)
+    })
+    .await?
+}
+
+/// Reply with JSON that's already been serialized.
+fn raw_json(body: &str) -> axum::response::Response {
+    (
+        [(header::CONTENT_TYPE, "application/json")],
+        body.to_string(),
+    )
+        .into_response()
+}
+
+/// Everything about the game, including players' tokens.
+pub async fn stats(GameRoom(room): GameRoom) -> impl IntoResponse {
+    raw_json(&room.view().stats)
+}
+
+/// The game as anyone watching gets to see it, unless players can only see
+/// so far, when only admins can.
+pub async fn spectate(
+    State(rooms): State<ServerState>,
+    GameRoom(room): GameRoom,
+    key: AdminKey,
+) -> Result<impl IntoResponse, GameError> {
+    let view = room.view();
+    key.can_spectate(&rooms, &view)?;
+    Ok(raw_json(&view.spectate))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LeaderboardQuery {
+    #[serde(default)]
+    sort: SortBy,
+}
+
+pub async fn leaderboard(
+    GameRoom(room): GameRoom,
+    Query(LeaderboardQuery { sort }): Query<LeaderboardQuery>,
+) -> impl IntoResponse {
+    let leaderboard = room.run(move |state| state.leaderboard(sort)).await;
+    leaderboard.map(Json)
+}
+
+/// How the most recent rounds turned out, oldest first.
+pub async fn rounds(GameRoom(room): GameRoom) -> impl IntoResponse {
+    let history = room.run(|state| state.history().to_vec()).await;
+    history.map(Json)
+}
+
+pub async fn reset(GameRoom(room): GameRoom) -> Result<StatusCode, GameError> {
+    room.run(GameState::reset).await?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn pause(GameRoom(room): GameRoom) -> Result<StatusCode, GameError> {
+    room.run(GameState::pause).await?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn resume(GameRoom(room): GameRoom) -> Result<StatusCode, GameError> {
+    room.run(GameState::resume).await?;
+    Ok(StatusCode::OK)
+}
+
+/// Every player with their token, idle time and score.
+pub async fn list_players(GameRoom(room): GameRoom) -> impl IntoResponse {
+    let players = room
+        .run(|state| serde_json::to_value(state.player_details()))
+        .await;
+    players.map(|players| Json(players.unwrap_or_default()))
+}
+
+pub async fn kick(
+    GameRoom(room): GameRoom,
+    Path(PlayerPath { pid }): Path<PlayerPath>,
+) -> Result<Json<FullResponse>, GameError> {
+    Ok(Json(room.run(move |state| state.kick(pid)).await??))
+}
+
+pub async fn teleport(
+    GameRoom(room): GameRoom,
+    Path(PlayerPath { pid }): Path<PlayerPath>,
+    body: Result<Json<TeleportBody>, JsonRejection>,
+) -> Result<StatusCode, GameError> {
+    let Json(TeleportBody { x, y }) = body.map_err(|e| GameError::BadRequest(e.body_text()))?;
+    room.run(move |state| state.teleport(pid, x, y)).await??;
+    Ok(StatusCode::OK)
+}
+
+pub async fn make_it(
+    GameRoom(room): GameRoom,
+    Path(PlayerPath { pid }): Path<PlayerPath>,
+) -> Result<StatusCode, GameError> {
+    room.run(move |state| state.make_it(pid)).await??;
+    Ok(StatusCode::OK)
+}
+
+pub async fn render_metrics(State(rooms): State<ServerState>) -> impl IntoResponse {
+    let body = metrics::render(&rooms).await;
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body).into_response()
+}
+
+pub async fn list_rooms(State(rooms): State<ServerState>) -> impl IntoResponse {
+    Json(rooms.summaries()).into_response()
+}
+
+/// Open a new room. The body can optionally be a JSON object with any game
+/// settings that should differ from the server's, e.g. `{"width": 50}`.
+pub async fn create_room(
+    State(rooms): State<ServerState>,
+    Path(RoomPath { room }): Path<RoomPath>,
+    headers: HeaderMap,
+    overrides: Result<Json<serde_json::Value>, JsonRejection>,
+) -> impl IntoResponse {
+    let config = match overrides {
+        Ok(Json(overrides)) => match rooms.config().with_overrides(overrides) {
+            Ok(config) => Some(config),
+            Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+        },
+        // no body at all means there's nothing to override, but a body that
+        // isn't JSON shouldn't quietly get the server's settings
+        Err(_) if !has_body(&headers) => None,
+        Err(e) => return (e.status(), e.body_text()).into_response(),
+    };
+    match rooms.create(&room, config) {
+        Ok(_) => (StatusCode::CREATED).into_response(),
         Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
     }
 }
 
-pub async fn quit(State(data): State<ServerState>, Path(pid): Path<u16>) -> impl IntoResponse {
-    let mut state = data.lock().await;
-    state.record_request();
-    match state.remove_player(pid) {
-        Ok(res) => Json(res).into_response(),
+/// Whether a request came with a body, which it can only do with a length or
+/// in chunks.
+fn has_body(headers: &HeaderMap) -> bool {
+    let length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok());
+    headers.contains_key(header::TRANSFER_ENCODING) || length.is_some_and(|length| length != "0")
+}
+
+pub async fn delete_room(
+    State(rooms): State<ServerState>,
+    Path(RoomPath { room }): Path<RoomPath>,
+) -> impl IntoResponse {
+    match rooms.remove(&room).await {
+        Ok(()) => (StatusCode::OK).into_response(),
         Err(e) => (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
     }
 }
 
-pub async fn stats(State(data): State<ServerState>) -> impl IntoResponse {
-    let state = data.lock().await;
-    Json(state.get_stats()).into_response()
+pub async fn get_snapshot(State(rooms): State<ServerState>) -> impl IntoResponse {
+    match snapshot::to_json(&rooms, false).await {
+        Ok(json) => ([(header::CONTENT_TYPE, "application/json")], json).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
 }
 
-pub async fn reset(State(data): State<ServerState>) -> impl IntoResponse {
-    let mut state = data.lock().await;
-    *state = GameState::default();
-    (StatusCode::OK).into_response()
+pub async fn save_snapshot(State(rooms): State<ServerState>) -> impl IntoResponse {
+    let Some(path) = rooms.snapshot() else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "the server has no snapshot file to save to",
+        )
+            .into_response();
+    };
+    match snapshot::save(&rooms, path).await {
+        Ok(()) => (StatusCode::OK, format!("saved to {}", path.display())).into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
 }
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::time::{Duration, Instant};
+
+/// How well a player has been doing since they joined.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Score {
+    tags_made: u32,
+    times_tagged: u32,
+    #[serde(default)]
+    rounds_won: u32,
+    it_time: Duration,
+    longest_survival: Duration,
+    /// When the player last became it or stopped being it. A restored player
+    /// starts a new stint.
+    #[serde(skip, default = "Instant::now")]
+    since: Instant,
+}
+
+/// A player's score as it stands right now, for the leaderboard.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ScoreCard {
+    pub id: u16,
+    pub name: String,
+    pub is_it: bool,
+    pub tags_made: u32,
+    pub times_tagged: u32,
+    pub rounds_won: u32,
+    pub it_time_secs: f64,
+    pub longest_survival_secs: f64,
+}
+
+/// What to rank the leaderboard by. Each puts the best players first, so
+/// fewer times tagged and less time as it rank higher.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    #[default]
+    TagsMade,
+    TimesTagged,
+    RoundsWon,
+    ItTime,
+    LongestSurvival,
+}
+
+impl Score {
+    pub fn new() -> Self {
+        Self {
+            tags_made: 0,
+            times_tagged: 0,
+            rounds_won: 0,
+            it_time: Duration::ZERO,
+            longest_survival: Duration::ZERO,
+            since: Instant::now(),
+        }
+    }
+
+    pub fn tagged_someone(&mut self) {
+        self.tags_made += 1;
+    }
+
+    pub fn got_tagged(&mut self) {
+        self.times_tagged += 1;
+    }
+
+    pub fn won_round(&mut self) {
+        self.rounds_won += 1;
+    }
+
+    /// Close out the stint that just ended when the player stops or starts
+    /// being it.
+    pub fn switch(&mut self, was_it: bool) {
+        let now = Instant::now();
+        let stint = now.duration_since(self.since);
+        if was_it {
+            self.it_time += stint;
+        } else {
+            self.longest_survival = self.longest_survival.max(stint);
+        }
+        self.since = now;
+    }
+
+    /// The score including the stint the player is in the middle of.
+    pub fn card(&self, id: u16, name: &str, is_it: bool) -> ScoreCard {
+        let stint = self.since.elapsed();
+        let (it_time, longest_survival) = if is_it {
+            (self.it_time + stint, self.longest_survival)
+        } else {
+            (self.it_time, self.longest_survival.max(stint))
+        };
+        ScoreCard {
+            id,
+            name: name.into(),
+            is_it,
+            tags_made: self.tags_made,
+            times_tagged: self.times_tagged,
+            rounds_won: self.rounds_won,
+            it_time_secs: it_time.as_secs_f64(),
+            longest_survival_secs: longest_survival.as_secs_f64(),
+        }
+    }
+}
+
+impl Default for Score {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SortBy {
+    pub fn sort(self, cards: &mut [ScoreCard]) {
+        match self {
+            Self::TagsMade => cards.sort_by_key(|c| Reverse(c.tags_made)),
+            Self::TimesTagged => cards.sort_by_key(|c| c.times_tagged),
+            Self::RoundsWon => cards.sort_by_key(|c| Reverse(c.rounds_won)),
+            Self::ItTime => cards.sort_by(|a, b| a.it_time_secs.total_cmp(&b.it_time_secs)),
+            Self::LongestSurvival => {
+                cards.sort_by(|a, b| b.longest_survival_secs.total_cmp(&a.longest_survival_secs))
+            }
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use anyhow::anyhow;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::time::interval;
+
+use super::rooms::{Rooms, ServerState};
+use super::state::GameState;
+
+/// Every room's `GameState` as a JSON object keyed by room name. Snapshots
+/// saved to disk include each player's token so bots can carry on after a
+/// restore, which means they need to be kept as private as the tokens.
+/// Without `tokens` they're left out, e.g. for snapshots sent over HTTP.
+pub async fn to_json(rooms: &Rooms, tokens: bool) -> anyhow::Result<String> {
+    let mut snapshot = serde_json::Map::new();
+    for (name, room) in rooms.all() {
+        let mut state = room.run(|state| serde_json::to_value(&*state)).await??;
+        if !tokens {
+            strip_tokens(&mut state);
+        }
+        snapshot.insert(name, state);
+    }
+    Ok(serde_json::to_string_pretty(&snapshot)?)
+}
+
+fn strip_tokens(state: &mut Value) {
+    let Some(players) = state.get_mut("players").and_then(Value::as_array_mut) else {
+        return;
+    };
+    for player in players.iter_mut().filter_map(Value::as_object_mut) {
+        player.remove("token");
+    }
+}
+
+/// Write a snapshot to `path`, going through a temporary file so a crash
+/// halfway through can't leave a broken snapshot behind.
+pub async fn save(rooms: &Rooms, path: &Path) -> anyhow::Result<()> {
+    let json = to_json(rooms, true).await?;
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, json)
+        .await
+        .map_err(|e| anyhow!("could not write snapshot {}: {e}", tmp.display()))?;
+    tokio::fs::rename(&tmp, path)
+        .await
+        .map_err(|e| anyhow!("could not write snapshot {}: {e}", path.display()))?;
+    Ok(())
+}
+
+/// Read the rooms saved in a snapshot, or `None` if it hasn't been saved yet.
+pub fn load(path: &Path) -> anyhow::Result<Option<BTreeMap<String, GameState>>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(anyhow!("could not read snapshot {}: {e}", path.display())),
+    };
+    let rooms = serde_json::from_str(&text)
+        .map_err(|e| anyhow!("could not load snapshot {}: {e}", path.display()))?;
+    Ok(Some(rooms))
+}
+
+/// Save every room to `path` every `every` for as long as the server runs.
+pub async fn autosave(rooms: ServerState, path: PathBuf, every: Duration) {
+    let mut interval = interval(every);
+    // the first tick is immediate and there's nothing new to save yet
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        match save(&rooms, &path).await {
+            Ok(()) => tracing::debug!("saved snapshot to {}", path.display()),
+            Err(e) => tracing::error!("{e}"),
+        }
+    }
+}
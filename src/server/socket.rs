@@ -0,0 +1,117 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::Path;
+use axum::response::IntoResponse;
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+
+use crate::api::{FrameKind, FullResponse, MoveDir, SocketFrame, SocketMessage};
+
+use super::engine::Room;
+use super::error::GameError;
+use super::routes::{authorize, play_turn, GameRoom, PlayerPath, Token};
+
+/// Upgrade to a websocket that lets a player send commands as JSON frames and
+/// get the game state pushed to them whenever the board changes.
+pub async fn ws(
+    upgrade: WebSocketUpgrade,
+    GameRoom(room): GameRoom,
+    Path(PlayerPath { pid }): Path<PlayerPath>,
+    token: Token,
+) -> impl IntoResponse {
+    let check = token.clone();
+    let authorized = room.run(move |state| authorize(state, pid, &check)).await;
+    if let Err(e) = authorized.and_then(|authorized| authorized) {
+        return e.into_response();
+    }
+    upgrade.on_upgrade(move |socket| play(socket, room, pid, token))
+}
+
+async fn play(socket: WebSocket, data: Room, pid: u16, token: Token) {
+    let (mut sender, mut receiver) = socket.split();
+    let Ok(changes) = data.run(|state| state.subscribe()).await else {
+        return;
+    };
+    let mut views = data.watch();
+    views.borrow_and_update();
+    // the version of the last board the player was told about, so the first
+    // push is a real change
+    let mut seen = *changes.borrow();
+
+    loop {
+        let (kind, reply) = tokio::select! {
+            frame = receiver.next() => match frame {
+                Some(Ok(Message::Text(text))) => {
+                    let reply = command(&data, pid, &token, &text).await;
+                    // this reply already includes our own move, so don't push it again
+                    seen = *changes.borrow();
+                    (FrameKind::Reply, reply)
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            changed = views.changed() => match changed {
+                // the room was closed, which the player gets told before
+                // being hung up on
+                Err(_) => (FrameKind::Push, Err(GameError::RoomClosed)),
+                Ok(()) => {
+                    // everyone's update comes from the same published view
+                    // rather than each socket asking the game for its own
+                    let view = views.borrow_and_update().clone();
+                    if view.board <= seen {
+                        continue;
+                    }
+                    seen = view.board;
+                    let update = view.sight
+                        .respond_to_player(pid)
+                        .ok_or(GameError::UnknownPlayer(pid));
+                    (FrameKind::Push, update)
+                }
+            },
+        };
+
+        let quit = reply.is_err() && !still_playing(&data, pid).await;
+        let message = match reply {
+            Ok(res) => SocketMessage::Update(res),
+            Err(e) => SocketMessage::Error(e.body()),
+        };
+        let Ok(text) = serde_json::to_string(&SocketFrame { kind, message }) else {
+            break;
+        };
+        if sender.send(Message::Text(text)).await.is_err() || quit {
+            break;
+        }
+    }
+    tracing::debug!("websocket closed for player {pid}");
+}
+
+/// A command as the player sent it. Unlike `api::Command`, the direction is
+/// left as it was sent so that it's checked the same way `/api/v1` checks it.
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "camelCase")]
+enum SentCommand {
+    Look,
+    Move { dir: String },
+}
+
+/// Take the player's turn just like the HTTP routes would.
+async fn command(
+    data: &Room,
+    pid: u16,
+    token: &Token,
+    text: &str,
+) -> Result<FullResponse, GameError> {
+    let command = serde_json::from_str::<SentCommand>(text)
+        .map_err(|e| GameError::BadRequest(e.to_string()))?;
+    let dir = match command {
+        SentCommand::Look => MoveDir::None,
+        SentCommand::Move { dir } => dir.parse().map_err(GameError::InvalidDirection)?,
+    };
+    play_turn(data, pid, dir, token.clone()).await
+}
+
+async fn still_playing(data: &Room, pid: u16) -> bool {
+    let res = data
+        .run(move |state| state.respond_to_player(pid).is_ok())
+        .await;
+    res.unwrap_or(false)
+}
@@ -0,0 +1,104 @@
+use axum::extract::State;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::IntoResponse;
+use futures_util::stream;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch};
+
+use super::engine::View;
+use super::error::GameError;
+use super::events::GameEvent;
+use super::rooms::ServerState;
+use super::routes::{AdminKey, GameRoom};
+
+/// Everything a spectator's stream is waiting on.
+struct Spectator {
+    /// Closed along with the room, which ends the stream.
+    views: watch::Receiver<Arc<View>>,
+    /// The version of the last board sent, since views are published for
+    /// things spectators can't see too.
+    board: u64,
+    feed: broadcast::Receiver<GameEvent>,
+}
+
+/// Stream the room to spectators as Server-Sent Events: a `board` event with
+/// the same JSON as `/spectate` whenever anything changes, and `join`, `tag`,
+/// `quit` and `reset` events as they happen. Like `/spectate`, this needs the
+/// admin key when players can only see so far.
+pub async fn events(
+    State(rooms): State<ServerState>,
+    GameRoom(room): GameRoom,
+    key: AdminKey,
+) -> Result<impl IntoResponse, GameError> {
+    key.can_spectate(&rooms, &room.view())?;
+    let feed = room.run(|state| state.subscribe_events()).await?;
+    let mut views = room.watch();
+    let (first, board_version) = {
+        let view = views.borrow_and_update();
+        (board(&view), view.board)
+    };
+    let spectator = Spectator {
+        views,
+        board: board_version,
+        feed,
+    };
+
+    let updates = stream::unfold(spectator, |mut spectator| async move {
+        let event = spectator.next().await?;
+        Some((Ok::<_, Infallible>(event), spectator))
+    });
+    let stream = futures_util::StreamExt::chain(stream::iter([Ok(first)]), updates);
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+impl Spectator {
+    /// Wait for the next thing worth telling the spectator, or `None` once
+    /// the room is gone.
+    async fn next(&mut self) -> Option<Event> {
+        loop {
+            tokio::select! {
+                changed = self.views.changed() => {
+                    changed.ok()?;
+                    let view = self.views.borrow_and_update().clone();
+                    if view.board != self.board {
+                        self.board = view.board;
+                        return Some(board(&view));
+                    }
+                }
+                event = self.feed.recv() => match event {
+                    Ok(event) => {
+                        if let Some(event) = discrete(&event) {
+                            return Some(event);
+                        }
+                    }
+                    // missing a few events is fine, the next board catches up
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return None,
+                },
+            }
+        }
+    }
+}
+
+fn board(view: &View) -> Event {
+    Event::default().event("board").data(&view.spectate)
+}
+
+fn discrete(event: &GameEvent) -> Option<Event> {
+    let name = match event {
+        GameEvent::Register { .. } => "join",
+        GameEvent::Tag { .. } => "tag",
+        GameEvent::Unfreeze { .. } => "unfreeze",
+        GameEvent::Quit { .. } => "quit",
+        GameEvent::Paused => "pause",
+        GameEvent::Resumed => "resume",
+        GameEvent::RoundStart { .. } => "round_start",
+        GameEvent::RoundOver { .. } => "round_over",
+        GameEvent::Reset => "reset",
+        _ => return None,
+    };
+    // SAFETY: events are plain data that always serialize
+    Some(Event::default().event(name).json_data(event).unwrap())
+}
@@ -1,197 +1,1609 @@
-use std::sync::Arc;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::anyhow;
+use rand::seq::SliceRandom;
 use rand::Rng;
-use serde::Serialize;
-use tokio::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use tokio::sync::{broadcast, oneshot, watch};
 
-use crate::api::{FullResponse, MoveDir, PartialResponse, PlayerLocation};
+use crate::api::{
+    FullResponse, GameMode, MoveDir, PartialResponse, PlayerLocation, RoundPhase, VisionMetric,
+};
 
-pub type ServerState = Arc<Mutex<GameState>>;
+use super::config::GameConfig;
+use super::error::GameError;
+use super::events::{EventLog, GameEvent, PlayerSpot, QuitReason};
+use super::grid::Grid;
+use super::ids::IdAllocator;
+use super::map::Map;
+use super::round::{Round, RoundResult, KEEP_HISTORY};
+use super::score::{Score, ScoreCard, SortBy};
+use super::tick::{self, Intent};
 
-#[derive(Clone, Debug, Serialize)]
+/// How many random tiles a new player tries before looking through the whole
+/// board for a free one.
+const RANDOM_SPAWN_TRIES: usize = 32;
+
+/// Everything about a game, which can be saved and restored as JSON. Use
+/// `spectate` for what's safe to show to everyone.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(try_from = "SavedGame")]
 pub struct GameState {
     players: Vec<Player>,
+    #[serde(flatten)]
+    config: GameConfig,
+    #[serde(flatten)]
+    map: Map,
+    stats: Stats,
+    /// The most recent finished rounds, oldest first.
+    history: Vec<RoundResult>,
+    #[serde(skip)]
+    round: Round,
+    /// Where everyone is, kept in step with `players`.
+    #[serde(skip)]
+    grid: Grid,
+    #[serde(skip)]
+    ids: IdAllocator,
+    /// When an admin paused the game, if they did.
+    #[serde(skip)]
+    paused_at: Option<Instant>,
+    /// Bumped every time the board changes so connected players can be told.
+    #[serde(skip)]
+    changes: watch::Sender<u64>,
+    /// Actions waiting for the next tick when moves are resolved in ticks.
+    #[serde(skip)]
+    queue: Vec<QueuedAction>,
+    #[serde(skip)]
+    events: Option<EventLog>,
+    /// Every event as it happens, for anyone watching.
+    #[serde(skip)]
+    feed: broadcast::Sender<GameEvent>,
+    /// When the tags in the last minute happened, for the metrics.
+    #[serde(skip)]
+    recent_tags: VecDeque<Instant>,
+}
+
+/// The parts of a saved `GameState` that can't be rebuilt from its config.
+#[derive(Deserialize)]
+struct SavedGame {
+    players: Vec<Player>,
+    #[serde(flatten)]
+    config: GameConfig,
+    stats: Stats,
+    #[serde(default)]
+    history: Vec<RoundResult>,
+}
+
+/// The game as shown on the watch page, without anyone's token.
+#[derive(Debug, Serialize)]
+pub struct Spectate<'a> {
+    players: Vec<SpectatedPlayer<'a>>,
+    #[serde(flatten)]
+    config: &'a GameConfig,
+    #[serde(flatten)]
+    map: &'a Map,
+    stats: &'a Stats,
+    paused: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    phase: Option<RoundPhase>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    time_remaining_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct SpectatedPlayer<'a> {
+    id: u16,
+    name: &'a str,
+    is_it: bool,
+    frozen: bool,
+    x: i16,
+    y: i16,
+}
+
+/// What every player is told about the game, whoever they are.
+#[derive(Clone, Debug)]
+struct Outlook {
     width: i16,
     height: i16,
-    stats: Stats,
+    vision_radius: Option<u16>,
+    vision_metric: VisionMetric,
+    mode: GameMode,
+    paused: bool,
+    phase: Option<RoundPhase>,
+    time_remaining_ms: Option<u64>,
+}
+
+/// What each player could see when a room's view was published, so players
+/// connected over a websocket can be told about changes without going
+/// through the game.
+#[derive(Debug)]
+pub struct Sight {
+    outlook: Outlook,
+    players: Vec<SeenPlayer>,
+    /// Each player's index in `players`.
+    index: HashMap<u16, usize>,
+}
+
+#[derive(Debug)]
+struct SeenPlayer {
+    id: u16,
+    name: String,
+    location: PlayerLocation,
+}
+
+/// Everything about a player, token included, for admins.
+#[derive(Debug, Serialize)]
+pub struct PlayerDetails<'a> {
+    id: u16,
+    name: &'a str,
+    token: &'a str,
+    is_it: bool,
+    frozen: bool,
+    immune: bool,
+    x: i16,
+    y: i16,
+    idle_secs: f64,
+    score: ScoreCard,
+}
+
+#[derive(Debug)]
+struct QueuedAction {
+    id: u16,
+    dir: MoveDir,
+    done: oneshot::Sender<Result<(), GameError>>,
+}
+
+/// What happened to an action handed to `GameState::submit`.
+#[derive(Debug)]
+pub enum Submitted {
+    /// The action has already been taken.
+    Done,
+    /// The action will be taken on the next tick, which resolves the receiver,
+    /// unless the game is paused first.
+    Queued(oneshot::Receiver<Result<(), GameError>>),
+    /// The player already has an action waiting for the next tick.
+    AlreadyQueued,
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct Stats {
     tags: usize,
     requests: usize,
 }
 
-impl Default for Stats {
-    fn default() -> Self {
-        Self {
-            tags: 0,
-            requests: 0,
-        }
-    }
-}
-
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Player {
     pub id: u16,
     name: String,
+    /// Players restored from a snapshot without tokens get new ones.
+    #[serde(default = "new_token")]
+    token: String,
     is_it: bool,
+    /// Tagged in freeze tag and stuck until a teammate comes to the rescue.
+    #[serde(default)]
+    frozen: bool,
     x: i16,
     y: i16,
+    #[serde(skip)]
+    last_action: Option<Instant>,
+    /// Restored players get a full idle timeout to reconnect.
+    #[serde(skip, default = "Instant::now")]
+    last_seen: Instant,
+    /// The player can't be tagged until then.
+    #[serde(skip)]
+    immune_until: Option<Instant>,
+    score: Score,
+    /// How the player is doing in just the current round.
+    #[serde(default)]
+    round_score: Score,
 }
 
 impl Default for GameState {
     fn default() -> Self {
-        Self {
-            players: Vec::new(),
-            width: 25,
-            height: 10,
-            stats: Stats::default(),
-        }
+        Self::new(GameConfig::default()).expect("default config has no map file to fail loading")
     }
 }
 
 impl GameState {
-    pub fn new_server_state() -> ServerState {
-        Arc::new(Mutex::new(Self {
+    pub fn new(mut config: GameConfig) -> anyhow::Result<Self> {
+        config.validate()?;
+        let map = match &config.map {
+            Some(path) => Map::load(path)?,
+            None => Map::open(config.width, config.height),
+        };
+        config.width = map.width;
+        config.height = map.height;
+
+        Ok(Self {
             players: Vec::new(),
-            width: 25,
-            height: 10,
+            grid: Grid::new(map.width, map.height),
+            ids: IdAllocator::default(),
+            map,
             stats: Stats::default(),
-        }))
+            history: Vec::new(),
+            round: Round::new(&config),
+            paused_at: None,
+            changes: watch::channel(0).0,
+            queue: Vec::new(),
+            events: None,
+            feed: broadcast::channel(64).0,
+            recent_tags: VecDeque::new(),
+            config,
+        })
+    }
+
+    pub fn config(&self) -> &GameConfig {
+        &self.config
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+
+    /// Clear all players, stats and rounds while keeping the server's
+    /// configuration.
+    pub fn reset(&mut self) {
+        for player in self.players.drain(..) {
+            self.ids.release(player.id);
+        }
+        self.grid = Grid::new(self.map.width, self.map.height);
+        self.stats = Stats::default();
+        self.history.clear();
+        self.round = Round::new(&self.config);
+        self.record(GameEvent::Reset);
+        self.changed();
     }
 
-    pub fn gen_player(&mut self) -> Player {
-        let id = rand::thread_rng().gen_range(1000..2000);
-        let (x, y) = self.random_unoccupied();
+    /// Send everything that happens from now on to `log`, starting with the
+    /// board as it is.
+    pub fn log_events(&mut self, log: EventLog) {
+        self.events = Some(log);
+        self.record(GameEvent::Open {
+            width: self.map.width,
+            height: self.map.height,
+            walls: self.map.walls.clone(),
+            players: self.players.iter().map(Player::spot).collect(),
+            mode: self.config.mode,
+        });
+    }
+
+    /// Log that the room is closing, the last thing that will happen in it.
+    pub fn close(&self) {
+        self.record(GameEvent::Close);
+    }
+
+    /// Subscribe to be notified whenever the board changes.
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.changes.subscribe()
+    }
+
+    /// Goes up by one every time the board changes.
+    pub fn board_version(&self) -> u64 {
+        *self.changes.borrow()
+    }
+
+    /// Subscribe to every event from now on.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<GameEvent> {
+        self.feed.subscribe()
+    }
+
+    pub fn gen_player(&mut self) -> anyhow::Result<Player> {
+        let cells = self.map.floor_tiles();
+        if self.players.len() >= self.config.max_players.min(cells) {
+            return Err(GameError::BoardFull.into());
+        }
+
+        let Some((x, y)) = self.random_unoccupied() else {
+            return Err(GameError::BoardFull.into());
+        };
+        let Some(id) = self.ids.allocate() else {
+            return Err(GameError::BoardFull.into());
+        };
+        let now = Instant::now();
         let player = Player {
             id,
             name: format!("Player {id}"),
+            token: new_token(),
             is_it: self.players.is_empty(),
+            frozen: false,
             x,
             y,
+            last_action: None,
+            last_seen: now,
+            immune_until: Some(now + self.config.spawn_immunity()),
+            score: Score::new(),
+            round_score: Score::new(),
         };
         self.players.push(player.clone());
-        player
+        self.grid.add(id, (x, y));
+        self.record(GameEvent::Register {
+            id,
+            name: player.name.clone(),
+            x,
+            y,
+            is_it: player.is_it,
+        });
+        self.changed();
+        Ok(player)
+    }
+
+    /// Add a new player to the game and give them the response containing the
+    /// secret token they'll need for every other request.
+    pub fn register(&mut self) -> anyhow::Result<FullResponse> {
+        let player = self.gen_player()?;
+        let mut response = self.respond_to_player(player.id)?;
+        response.token = Some(player.token);
+        response.walls = self.map.walls.clone();
+        Ok(response)
+    }
+
+    /// Check that the token presented with a request belongs to the player.
+    pub fn authorize(&self, id: u16, token: Option<&str>) -> anyhow::Result<bool> {
+        let player = self.get_player(id)?;
+        // compared in constant time, like the admin key, so a token can't be
+        // guessed a byte at a time
+        Ok(token.is_some_and(|t| t.as_bytes().ct_eq(player.token.as_bytes()).into()))
+    }
+
+    /// Take a move or look for the player, or queue it up for the next tick
+    /// if the game resolves moves in ticks.
+    pub fn submit(&mut self, id: u16, dir: MoveDir) -> anyhow::Result<Submitted> {
+        self.get_player(id)?;
+        if self.paused_at.is_some() && dir != MoveDir::None {
+            return Err(GameError::Paused.into());
+        }
+        if self.config.tick().is_none() {
+            self.move_player(id, &dir)?;
+            return Ok(Submitted::Done);
+        }
+
+        if self.queue.iter().any(|a| a.id == id) {
+            return Ok(Submitted::AlreadyQueued);
+        }
+        self.check_not_frozen(id, &dir)?;
+        let (done, queued) = oneshot::channel();
+        self.queue.push(QueuedAction { id, dir, done });
+        Ok(Submitted::Queued(queued))
     }
 
     pub fn move_player(&mut self, id: u16, dir: &MoveDir) -> anyhow::Result<()> {
+        self.check_not_frozen(id, dir)?;
         let idx = self.get_player_index(id)?;
-        let (dx, dy) = match dir {
-            MoveDir::Up => (0, 1),
-            MoveDir::Down => (0, -1),
-            MoveDir::Left => (-1, 0),
-            MoveDir::Right => (1, 0),
-            MoveDir::None => (0, 0),
-        };
-
+        let (dx, dy) = delta(dir);
         let (nx, ny) = (self.players[idx].x + dx, self.players[idx].y + dy);
 
-        if self.occupied(nx, ny) {
-            // SAFETY: just tested for player at pos, should not panic
-            let j = self.get_player_index_at(nx, ny).unwrap();
-            if self.players[idx].is_it || self.players[j].is_it {
-                self.stats.tags += 1;
-                self.players[idx].is_it = !self.players[idx].is_it;
-                self.players[j].is_it = !self.players[j].is_it;
-            }
+        // walls and the edge of the map don't budge
+        if self.map.blocked(nx, ny) {
+            self.record_action(idx, dir);
+            return Ok(());
+        }
+
+        if let Ok(j) = self.get_player_index_at(nx, ny) {
+            self.record_action(idx, dir);
+            self.collide(idx, j);
         } else {
-            self.players[idx].x = nx;
-            self.players[idx].y = ny;
+            if (dx, dy) != (0, 0) {
+                self.place(idx, nx, ny);
+                self.changed();
+            }
+            self.record_action(idx, dir);
         }
         Ok(())
     }
 
+    /// Make every queued move at once and let everyone waiting on the tick
+    /// know it's done. See `tick::resolve` for how conflicts are settled.
+    pub fn resolve_tick(&mut self) {
+        let queue = std::mem::take(&mut self.queue);
+        let mut intents = Vec::new();
+        for action in &queue {
+            let Ok(idx) = self.get_player_index(action.id) else {
+                continue;
+            };
+            let (dx, dy) = delta(&action.dir);
+            let (x, y) = (self.players[idx].x, self.players[idx].y);
+            let stuck = self.players[idx].frozen || self.map.blocked(x + dx, y + dy);
+            if (dx, dy) != (0, 0) && !stuck {
+                intents.push(Intent {
+                    id: action.id,
+                    from: (x, y),
+                    to: (x + dx, y + dy),
+                });
+            }
+        }
+        let standing: Vec<_> = self
+            .players
+            .iter()
+            .filter(|p| !intents.iter().any(|i| i.id == p.id))
+            .map(|p| (p.id, (p.x, p.y)))
+            .collect();
+
+        let outcome = tick::resolve(&standing, &intents);
+        for (id, (x, y)) in outcome.moves {
+            // SAFETY: intents were only made for players that exist
+            let idx = self.get_player_index(id).unwrap();
+            self.place(idx, x, y);
+            self.changed();
+        }
+        for action in &queue {
+            if let Ok(idx) = self.get_player_index(action.id) {
+                self.record_action(idx, &action.dir);
+            }
+        }
+        // a player can only be part of one tag per tick, lowest ids first
+        let mut tagged = Vec::new();
+        for (a, b) in outcome.collisions {
+            if tagged.contains(&a) || tagged.contains(&b) {
+                continue;
+            }
+            // SAFETY: collisions are only between players that exist
+            let (i, j) = (
+                self.get_player_index(a).unwrap(),
+                self.get_player_index(b).unwrap(),
+            );
+            if self.collide(i, j) {
+                tagged.extend([a, b]);
+            }
+        }
+
+        for action in queue {
+            // the player may have given up waiting, which is fine
+            let _ = action.done.send(Ok(()));
+        }
+    }
+
     pub fn respond_to_player(&self, id: u16) -> anyhow::Result<FullResponse> {
-        let map_height = self.height;
-        let map_width = self.width;
-        let players = self.get_other_players(id);
         let current_player = self.get_player(id)?;
+        let players = self.get_visible_players(current_player);
+        Ok(self.outlook().respond(
+            current_player.id,
+            &current_player.name,
+            current_player.location(),
+            players,
+        ))
+    }
 
-        Ok(FullResponse {
-            id: current_player.id,
-            name: current_player.name.clone(),
-            map_height,
-            map_width,
-            inner: PartialResponse {
-                is_it: current_player.is_it,
-                players,
-                x: current_player.x,
-                y: current_player.y,
-            },
-        })
+    /// What every player can see right now, to answer them from later.
+    pub fn sight(&self) -> Sight {
+        let players: Vec<_> = self
+            .players
+            .iter()
+            .map(|p| SeenPlayer {
+                id: p.id,
+                name: p.name.clone(),
+                location: p.location(),
+            })
+            .collect();
+        Sight {
+            outlook: self.outlook(),
+            index: self.grid.indexes(),
+            players,
+        }
     }
 
-    pub fn remove_player(&mut self, id: u16) -> anyhow::Result<FullResponse> {
-        let response = self.respond_to_player(id);
-        let idx = self.get_player_index(id)?;
-        self.players.remove(idx);
-        self.random_it();
-        response
+    fn outlook(&self) -> Outlook {
+        Outlook {
+            width: self.map.width,
+            height: self.map.height,
+            vision_radius: self.config.vision_radius,
+            vision_metric: self.config.vision_metric,
+            mode: self.config.mode,
+            paused: self.paused_at.is_some(),
+            phase: self.phase(),
+            time_remaining_ms: self.time_remaining_ms(),
+        }
     }
 
-    pub fn occupied(&self, x: i16, y: i16) -> bool {
-        self.players.iter().any(|p| p.x == x && p.y == y)
+    pub fn remove_player(&mut self, id: u16) -> anyhow::Result<FullResponse> {
+        self.remove(id, QuitReason::Quit)
     }
 
-    pub fn get_stats(&self) -> &GameState {
-        &self
+    /// Remove every player that hasn't taken an action within `timeout`,
+    /// returning the ids of the players that were removed.
+    pub fn remove_idle(&mut self) -> Vec<u16> {
+        // nobody can do anything while the game is paused
+        if self.paused_at.is_some() {
+            return Vec::new();
+        }
+        let timeout = self.config.idle_timeout();
+        let idle: Vec<u16> = self
+            .players
+            .iter()
+            .filter(|p| p.last_seen.elapsed() >= timeout)
+            .map(|p| p.id)
+            .collect();
+        for id in &idle {
+            // SAFETY: ids were just collected from the current players
+            self.remove(*id, QuitReason::Idle).unwrap();
+        }
+        idle
     }
 
-    pub fn record_request(&mut self) {
-        self.stats.requests += 1;
+    pub fn occupied(&self, x: i16, y: i16) -> bool {
+        self.grid.at(x, y).is_some()
     }
 
-    fn get_player(&self, id: u16) -> anyhow::Result<&Player> {
-        self.players
-            .iter()
-            .find(|p| p.id == id)
-            .ok_or(anyhow!("could not find player with id {id}"))
+    pub fn spectate(&self) -> Spectate<'_> {
+        Spectate {
+            players: self
+                .players
+                .iter()
+                .map(|p| SpectatedPlayer {
+                    id: p.id,
+                    name: &p.name,
+                    is_it: p.is_it,
+                    frozen: p.frozen,
+                    x: p.x,
+                    y: p.y,
+                })
+                .collect(),
+            config: &self.config,
+            map: &self.map,
+            stats: &self.stats,
+            paused: self.paused_at.is_some(),
+            phase: self.phase(),
+            time_remaining_ms: self.time_remaining_ms(),
+        }
     }
 
-    fn get_player_index(&mut self, id: u16) -> anyhow::Result<usize> {
-        self.players
-            .iter()
-            .position(|p| p.id == id)
-            .ok_or(anyhow!("could not find player with id {id}"))
+    /// Record an action for the player unless they've acted too recently, in
+    /// which case nothing is recorded and the time left to wait is returned.
+    pub fn throttle(&mut self, id: u16) -> Option<Duration> {
+        let interval = self.config.action_interval();
+        let player = &mut self.players[self.grid.index_of(id)?];
+        let now = Instant::now();
+        if let Some(last) = player.last_action {
+            let elapsed = now.duration_since(last);
+            if elapsed < interval {
+                return Some(interval - elapsed);
+            }
+        }
+        player.last_action = Some(now);
+        player.last_seen = now;
+        None
     }
 
-    fn get_player_index_at(&mut self, x: i16, y: i16) -> anyhow::Result<usize> {
-        self.players
+    /// Everyone's scores, best first by `sort`.
+    pub fn leaderboard(&self, sort: SortBy) -> Vec<ScoreCard> {
+        let mut cards: Vec<_> = self
+            .players
             .iter()
-            .position(|p| p.x == x && p.y == y)
-            .ok_or(anyhow!("could not find player at position ({x}, {y})"))
+            .map(|p| p.score.card(p.id, &p.name, p.is_it))
+            .collect();
+        sort.sort(&mut cards);
+        cards
     }
 
-    fn get_other_players(&self, id: u16) -> Vec<PlayerLocation> {
+    /// Every player with everything about them, for admins.
+    pub fn player_details(&self) -> Vec<PlayerDetails<'_>> {
         self.players
             .iter()
-            .filter(|p| p.id != id)
-            .map(|p| PlayerLocation {
+            .map(|p| PlayerDetails {
+                id: p.id,
+                name: &p.name,
+                token: &p.token,
                 is_it: p.is_it,
+                frozen: p.frozen,
+                immune: p.immune(),
                 x: p.x,
                 y: p.y,
+                idle_secs: p.last_seen.elapsed().as_secs_f64(),
+                score: p.score.card(p.id, &p.name, p.is_it),
             })
             .collect()
     }
 
-    fn random_it(&mut self) {
+    /// Throw a player out of the game, e.g. a bot that's stuck.
+    pub fn kick(&mut self, id: u16) -> anyhow::Result<FullResponse> {
+        self.remove(id, QuitReason::Kicked)
+    }
+
+    /// Put a player on any open tile, no matter how far away.
+    pub fn teleport(&mut self, id: u16, x: i16, y: i16) -> anyhow::Result<()> {
+        let idx = self.get_player_index(id)?;
+        if self.map.blocked(x, y) {
+            return Err(anyhow!("({x}, {y}) is a wall or off the map"));
+        }
+        if self.grid.at(x, y).is_some_and(|other| other != id) {
+            return Err(anyhow!("({x}, {y}) already has a player on it"));
+        }
+        self.place(idx, x, y);
+        self.record(GameEvent::Teleport { id, x, y });
+        self.changed();
+        Ok(())
+    }
+
+    /// Make a player it. In infection that adds them to the it team, in the
+    /// other modes they take over from whoever was it.
+    pub fn make_it(&mut self, id: u16) -> anyhow::Result<()> {
+        let idx = self.get_player_index(id)?;
+        if self.config.mode != GameMode::Infection {
+            for player in &mut self.players {
+                player.set_it(false);
+            }
+        }
+        self.players[idx].set_it(true);
+        self.players[idx].frozen = false;
+        self.record(GameEvent::MadeIt { id });
+        self.changed();
+        Ok(())
+    }
+
+    /// Stop everyone from moving until the game is resumed. Looking is still
+    /// fine, and round timers and idle timeouts stand still in the meantime.
+    pub fn pause(&mut self) {
+        if self.paused_at.is_none() {
+            self.paused_at = Some(Instant::now());
+            // moves waiting on the next tick would be made during the pause, so
+            // they're turned down like any other move now, but looks can stay
+            let (moves, looks): (Vec<_>, Vec<_>) = std::mem::take(&mut self.queue)
+                .into_iter()
+                .partition(|action| action.dir != MoveDir::None);
+            self.queue = looks;
+            for action in moves {
+                let _ = action.done.send(Err(GameError::Paused));
+            }
+            self.record(GameEvent::Paused);
+            self.changed();
+        }
+    }
+
+    pub fn resume(&mut self) {
+        let Some(paused_at) = self.paused_at.take() else {
+            return;
+        };
+        let paused_for = paused_at.elapsed();
+        if let Some(until) = &mut self.round.until {
+            *until += paused_for;
+        }
+        let now = Instant::now();
+        for player in &mut self.players {
+            player.last_seen = now;
+            // immunity is for playing time, so none of it is used up by the pause
+            if let Some(until) = player.immune_until.as_mut().filter(|u| **u > paused_at) {
+                *until += paused_for;
+            }
+        }
+        self.record(GameEvent::Resumed);
+        self.changed();
+    }
+
+    /// The results of the most recent rounds, oldest first.
+    pub fn history(&self) -> &[RoundResult] {
+        &self.history
+    }
+
+    /// Move the round on to its next phase once its time is up, or once enough
+    /// players have joined or left. Does nothing unless the game is played in
+    /// timed rounds.
+    pub fn advance_round(&mut self) {
+        let Some(length) = self.config.round() else {
+            return;
+        };
+        if self.paused_at.is_some() {
+            return;
+        }
+        let enough = self.players.len() >= self.config.min_players.max(1);
+        let up = self.round.time_remaining().is_some_and(|t| t.is_zero());
+        match self.round.phase {
+            RoundPhase::Lobby if enough => self.start_round(),
+            RoundPhase::Warmup if !enough => self.set_phase(RoundPhase::Lobby, None),
+            RoundPhase::Warmup if up => {
+                // only what happens from here on counts for the round
+                for player in &mut self.players {
+                    player.round_score = Score::new();
+                }
+                self.set_phase(RoundPhase::Playing, Some(length));
+            }
+            RoundPhase::Playing if up => self.finish_round(None),
+            RoundPhase::Playing if !enough => self.abandon_round(),
+            RoundPhase::Over if up && enough => self.start_round(),
+            RoundPhase::Over if up => self.set_phase(RoundPhase::Lobby, None),
+            _ => {}
+        }
+    }
+
+    pub fn tags(&self) -> usize {
+        self.stats.tags
+    }
+
+    pub fn tags_last_minute(&mut self) -> usize {
+        let minute = Duration::from_secs(60);
+        while self
+            .recent_tags
+            .front()
+            .is_some_and(|t| t.elapsed() > minute)
+        {
+            self.recent_tags.pop_front();
+        }
+        self.recent_tags.len()
+    }
+
+    pub fn record_request(&mut self) {
+        self.stats.requests += 1;
+    }
+
+    /// Frozen players can look around but not move.
+    fn check_not_frozen(&self, id: u16, dir: &MoveDir) -> anyhow::Result<()> {
+        if *dir != MoveDir::None && self.get_player(id)?.frozen {
+            return Err(GameError::Frozen(id).into());
+        }
+        Ok(())
+    }
+
+    /// Two players ran into each other, which is a tag if exactly one of them
+    /// is it and the other isn't immune or already frozen. What a tag does
+    /// depends on the game mode. Returns whether it was a tag or a rescue.
+    fn collide(&mut self, a: usize, b: usize) -> bool {
+        if !self.in_play() {
+            return false;
+        }
+        if self.players[a].is_it == self.players[b].is_it {
+            return self.rescue(a, b);
+        }
+        let (tagger, tagged) = if self.players[a].is_it {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        if self.players[tagged].immune() || self.players[tagged].frozen {
+            return false;
+        }
+        self.stats.tags += 1;
+        self.recent_tags.push_back(Instant::now());
+        self.players[tagger].tagged_someone();
+        self.players[tagged].got_tagged();
+        self.players[tagged].immune_until = None;
+        match self.config.mode {
+            GameMode::Classic => {
+                self.players[tagger].set_it(false);
+                // whoever just tagged can't be tagged straight back
+                self.players[tagger].immune_until =
+                    Some(Instant::now() + self.config.tag_back_immunity());
+                self.players[tagged].set_it(true);
+            }
+            GameMode::Infection => self.players[tagged].set_it(true),
+            GameMode::Freeze => self.players[tagged].frozen = true,
+        }
+        let (tagger, tagged) = (self.players[tagger].id, self.players[tagged].id);
+        self.record(GameEvent::Tag { tagger, tagged });
+        let winner = match self.config.mode {
+            GameMode::Freeze => tagger,
+            _ => tagged,
+        };
+        self.end_round_if_over(Some(winner));
+        self.changed();
+        true
+    }
+
+    /// Two players on the same team ran into each other, which thaws one of
+    /// them out if they were frozen. Returns whether anyone was rescued.
+    fn rescue(&mut self, a: usize, b: usize) -> bool {
+        let (rescuer, rescued) = match (self.players[a].frozen, self.players[b].frozen) {
+            (false, true) => (a, b),
+            (true, false) => (b, a),
+            _ => return false,
+        };
+        self.players[rescued].frozen = false;
+        self.record(GameEvent::Unfreeze {
+            rescuer: self.players[rescuer].id,
+            rescued: self.players[rescued].id,
+        });
+        self.changed();
+        true
+    }
+
+    /// The round is over once there's nobody left to infect in infection, or
+    /// once every runner is frozen in freeze tag. In infection `winner` was
+    /// the last one standing, in freeze tag the one who froze them.
+    fn end_round_if_over(&mut self, winner: Option<u16>) {
+        let runners = || self.players.iter().filter(|p| !p.is_it);
+        let over = self.in_play()
+            && self.players.len() > 1
+            && match self.config.mode {
+                GameMode::Classic => false,
+                GameMode::Infection => runners().count() == 0,
+                GameMode::Freeze => runners().all(|p| p.frozen),
+            };
+        if over {
+            self.finish_round(winner);
+        }
+    }
+
+    /// Whether tags count right now, which with timed rounds is only while a
+    /// round is being played.
+    fn in_play(&self) -> bool {
+        self.config.round().is_none() || self.round.phase == RoundPhase::Playing
+    }
+
+    fn phase(&self) -> Option<RoundPhase> {
+        self.config.round().map(|_| self.round.phase)
+    }
+
+    fn time_remaining_ms(&self) -> Option<u64> {
+        self.config.round()?;
+        self.round.time_remaining().map(|t| t.as_millis() as u64)
+    }
+
+    fn set_phase(&mut self, phase: RoundPhase, lasts: Option<Duration>) {
+        self.round.phase = phase;
+        self.round.until = lasts.map(|lasts| Instant::now() + lasts);
+        self.changed();
+    }
+
+    /// Start the next timed round with its warmup.
+    fn start_round(&mut self) {
+        self.round.number += 1;
+        self.fresh_round();
+        let it = self.random_it();
+        self.record(GameEvent::RoundStart {
+            round: self.round.number,
+            it,
+        });
+        self.set_phase(RoundPhase::Warmup, Some(self.config.warmup()));
+    }
+
+    /// Score the round and add it to the history. `winner` is who won if the
+    /// round was won outright, otherwise it goes to the best of the
+    /// standings. Timed rounds then wait out the intermission, otherwise the
+    /// next round starts straight away with someone at random as it.
+    fn finish_round(&mut self, winner: Option<u16>) {
+        let mut standings: Vec<_> = self
+            .players
+            .iter()
+            .map(|p| p.round_score.card(p.id, &p.name, p.is_it))
+            .collect();
+        round_ranking(self.config.mode).sort(&mut standings);
+        let winner = winner.or_else(|| standings.first().map(|card| card.id));
+        if let Some(idx) = winner.and_then(|id| self.grid.index_of(id)) {
+            self.players[idx].score.won_round();
+        }
+        let ended_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        self.history.push(RoundResult {
+            round: self.round.number,
+            ended_ms,
+            winner,
+            standings,
+        });
+        if self.history.len() > KEEP_HISTORY {
+            self.history.remove(0);
+        }
+
+        self.fresh_round();
+        let new_it = match self.config.round() {
+            Some(_) => {
+                self.set_phase(RoundPhase::Over, Some(self.config.intermission()));
+                None
+            }
+            None => {
+                self.round.number += 1;
+                self.random_it()
+            }
+        };
+        self.record(GameEvent::RoundOver { winner, new_it });
+        self.changed();
+    }
+
+    /// Call off a round that's lost too many players to go on. Nobody wins it
+    /// and it's left out of the history, and the room goes back to waiting for
+    /// players.
+    fn abandon_round(&mut self) {
+        self.fresh_round();
+        self.record(GameEvent::RoundOver {
+            winner: None,
+            new_it: None,
+        });
+        self.set_phase(RoundPhase::Lobby, None);
+    }
+
+    /// Everyone thaws out and goes back to being a runner with a clean score
+    /// for the round.
+    fn fresh_round(&mut self) {
+        for player in &mut self.players {
+            player.set_it(false);
+            player.frozen = false;
+            player.round_score = Score::new();
+        }
+    }
+
+    fn remove(&mut self, id: u16, reason: QuitReason) -> anyhow::Result<FullResponse> {
+        let response = self.respond_to_player(id);
+        let idx = self.get_player_index(id)?;
+        let removed = self.players.remove(idx);
+        let after = self.players[idx..].iter().map(|p| p.id);
+        self.grid.remove(id, (removed.x, removed.y), after);
+        self.ids.release(id);
+        let new_it = if removed.is_it && !self.players.iter().any(|p| p.is_it) {
+            self.random_it()
+        } else {
+            None
+        };
+        self.record(GameEvent::Quit { id, reason, new_it });
+        // the last player left to infect may have just quit
+        self.end_round_if_over(None);
+        self.changed();
+        response
+    }
+
+    fn record(&self, event: GameEvent) {
+        if let Some(events) = &self.events {
+            events.record(event.clone());
+        }
+        // nobody may be watching, which is fine
+        let _ = self.feed.send(event);
+    }
+
+    /// Log a move or look, with where the player ended up for a move.
+    fn record_action(&self, idx: usize, dir: &MoveDir) {
+        let player = &self.players[idx];
+        self.record(match dir {
+            MoveDir::None => GameEvent::Look { id: player.id },
+            dir => GameEvent::Move {
+                id: player.id,
+                dir: dir.clone(),
+                x: player.x,
+                y: player.y,
+            },
+        });
+    }
+
+    /// Move the player to a tile, wherever they are now.
+    fn place(&mut self, idx: usize, x: i16, y: i16) {
+        let player = &mut self.players[idx];
+        self.grid.moved(player.id, (player.x, player.y), (x, y));
+        player.x = x;
+        player.y = y;
+    }
+
+    fn changed(&self) {
+        self.changes.send_modify(|version| *version += 1);
+    }
+
+    fn get_player(&self, id: u16) -> anyhow::Result<&Player> {
+        Ok(&self.players[self.get_player_index(id)?])
+    }
+
+    fn get_player_index(&self, id: u16) -> anyhow::Result<usize> {
+        self.grid
+            .index_of(id)
+            .ok_or_else(|| GameError::UnknownPlayer(id).into())
+    }
+
+    fn get_player_index_at(&self, x: i16, y: i16) -> anyhow::Result<usize> {
+        self.grid
+            .at(x, y)
+            .and_then(|id| self.grid.index_of(id))
+            .ok_or(anyhow!("could not find player at position ({x}, {y})"))
+    }
+
+    /// Everyone else the player can see, which is everyone unless the vision
+    /// radius is limited. A small enough radius only looks at the tiles
+    /// around the player instead of going through everyone.
+    fn get_visible_players(&self, player: &Player) -> Vec<PlayerLocation> {
+        let metric = self.config.vision_metric;
+        let visible = |p: &Player| match self.config.vision_radius {
+            Some(radius) => metric.within(p.x - player.x, p.y - player.y, radius),
+            None => true,
+        };
+        let nearby: Vec<&Player> = match self.config.vision_radius {
+            Some(radius) if (2 * radius as usize + 1).pow(2) < self.players.len() => {
+                let r = radius.min(i16::MAX as u16) as i16;
+                let (xs, ys) = (
+                    player.x.saturating_sub(r)..=player.x.saturating_add(r),
+                    player.y.saturating_sub(r)..=player.y.saturating_add(r),
+                );
+                ys.flat_map(|y| xs.clone().map(move |x| (x, y)))
+                    .filter_map(|(x, y)| self.get_player_index_at(x, y).ok())
+                    .map(|idx| &self.players[idx])
+                    .collect()
+            }
+            _ => self.players.iter().collect(),
+        };
+        nearby
+            .into_iter()
+            .filter(|p| p.id != player.id && visible(p))
+            .map(Player::location)
+            .collect()
+    }
+
+    fn random_it(&mut self) -> Option<u16> {
         let player_count = self.players.len();
-        if player_count != 0 {
-            let new_it = rand::thread_rng().gen_range(0..player_count);
-            self.players[new_it].is_it = true;
+        if player_count == 0 {
+            return None;
         }
+        let new_it = rand::thread_rng().gen_range(0..player_count);
+        self.players[new_it].set_it(true);
+        self.players[new_it].frozen = false;
+        Some(self.players[new_it].id)
     }
 
-    fn random_unoccupied(&self) -> (i16, i16) {
+    /// A free spawn point, or any free floor if the map has none or they're
+    /// all taken, or `None` if there's nowhere left to put anyone.
+    fn random_unoccupied(&self) -> Option<(i16, i16)> {
         let mut rng = rand::thread_rng();
-        loop {
-            let x = rng.gen_range(0..self.width);
-            let y = rng.gen_range(0..self.height);
-            if !self.occupied(x, y) {
-                return (x, y);
+        let free = |&(x, y): &(i16, i16)| !self.map.blocked(x, y) && !self.occupied(x, y);
+        let spawns: Vec<_> = self.map.spawns.iter().copied().filter(free).collect();
+        if let Some(&pos) = spawns.choose(&mut rng) {
+            return Some(pos);
+        }
+
+        // guessing is quickest while the board is mostly empty
+        for _ in 0..RANDOM_SPAWN_TRIES {
+            let pos = (
+                rng.gen_range(0..self.map.width),
+                rng.gen_range(0..self.map.height),
+            );
+            if free(&pos) {
+                return Some(pos);
+            }
+        }
+        let empty = self.grid.empty(|x, y| self.map.blocked(x, y));
+        empty.choose(&mut rng).copied()
+    }
+}
+
+impl TryFrom<SavedGame> for GameState {
+    type Error = anyhow::Error;
+
+    fn try_from(saved: SavedGame) -> anyhow::Result<Self> {
+        let mut state = Self::new(saved.config)?;
+        for player in &saved.players {
+            if state.map.blocked(player.x, player.y) {
+                return Err(anyhow!(
+                    "player {} is stuck in a wall at ({}, {})",
+                    player.id,
+                    player.x,
+                    player.y
+                ));
+            }
+        }
+        for player in &saved.players {
+            if let Some(other) = state.grid.at(player.x, player.y) {
+                return Err(anyhow!(
+                    "players {other} and {} are both at ({}, {})",
+                    player.id,
+                    player.x,
+                    player.y
+                ));
             }
+            if !state.ids.claim(player.id) {
+                return Err(anyhow!("there's more than one player {}", player.id));
+            }
+            state.grid.add(player.id, (player.x, player.y));
+        }
+        state.players = saved.players;
+        state.stats = saved.stats;
+        state.history = saved.history;
+        Ok(state)
+    }
+}
+
+impl Player {
+    fn spot(&self) -> PlayerSpot {
+        PlayerSpot {
+            id: self.id,
+            name: self.name.clone(),
+            x: self.x,
+            y: self.y,
+            is_it: self.is_it,
+            frozen: self.frozen,
+        }
+    }
+
+    /// Where the player is, as others see them.
+    fn location(&self) -> PlayerLocation {
+        PlayerLocation {
+            is_it: self.is_it,
+            frozen: self.frozen,
+            immune: self.immune(),
+            x: self.x,
+            y: self.y,
+        }
+    }
+
+    fn immune(&self) -> bool {
+        self.immune_until
+            .is_some_and(|until| Instant::now() < until)
+    }
+
+    fn set_it(&mut self, is_it: bool) {
+        if self.is_it != is_it {
+            self.score.switch(self.is_it);
+            self.round_score.switch(self.is_it);
+            self.is_it = is_it;
+        }
+    }
+
+    fn tagged_someone(&mut self) {
+        self.score.tagged_someone();
+        self.round_score.tagged_someone();
+    }
+
+    fn got_tagged(&mut self) {
+        self.score.got_tagged();
+        self.round_score.got_tagged();
+    }
+}
+
+impl Outlook {
+    /// Everything a player is told, given who they are and who they can see.
+    fn respond(
+        &self,
+        id: u16,
+        name: &str,
+        me: PlayerLocation,
+        players: Vec<PlayerLocation>,
+    ) -> FullResponse {
+        FullResponse {
+            id,
+            name: name.into(),
+            token: None,
+            walls: Vec::new(),
+            vision_radius: self.vision_radius,
+            vision_metric: self.vision_radius.map(|_| self.vision_metric),
+            mode: self.mode,
+            phase: self.phase,
+            time_remaining_ms: self.time_remaining_ms,
+            map_height: self.height,
+            map_width: self.width,
+            inner: PartialResponse {
+                is_it: me.is_it,
+                frozen: me.frozen,
+                paused: self.paused,
+                players,
+                x: me.x,
+                y: me.y,
+            },
+        }
+    }
+}
+
+impl Sight {
+    /// What the player could see, or `None` if they weren't playing.
+    pub fn respond_to_player(&self, id: u16) -> Option<FullResponse> {
+        let me = &self.players[*self.index.get(&id)?];
+        let (x, y) = (me.location.x, me.location.y);
+        let visible = |p: &PlayerLocation| match self.outlook.vision_radius {
+            Some(radius) => self.outlook.vision_metric.within(p.x - x, p.y - y, radius),
+            None => true,
+        };
+        let players = self
+            .players
+            .iter()
+            .filter(|p| p.id != id && visible(&p.location))
+            .map(|p| p.location.clone())
+            .collect();
+        Some(
+            self.outlook
+                .respond(me.id, &me.name, me.location.clone(), players),
+        )
+    }
+}
+
+/// How a round that runs out of time is won: the least time as it in classic
+/// tag, surviving the longest in infection and the most freezes in freeze tag.
+fn round_ranking(mode: GameMode) -> SortBy {
+    match mode {
+        GameMode::Classic => SortBy::ItTime,
+        GameMode::Infection => SortBy::LongestSurvival,
+        GameMode::Freeze => SortBy::TagsMade,
+    }
+}
+
+fn delta(dir: &MoveDir) -> (i16, i16) {
+    match dir {
+        MoveDir::Up => (0, 1),
+        MoveDir::Down => (0, -1),
+        MoveDir::Left => (-1, 0),
+        MoveDir::Right => (1, 0),
+        MoveDir::None => (0, 0),
+    }
+}
+
+/// A secret for a player to prove who they are with.
+fn new_token() -> String {
+    format!("{:032x}", rand::thread_rng().gen::<u128>())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn arena() -> GameState {
+        GameState::new(GameConfig {
+            map: Some(concat!(env!("CARGO_MANIFEST_DIR"), "/maps/arena.txt").into()),
+            max_players: 1000,
+            ..GameConfig::default()
+        })
+        .unwrap()
+    }
+
+    /// A game of `mode` on `board` with a player on each `S`, and their ids in
+    /// the order the `S`s come in. The first of them is it.
+    fn on_board(mode: GameMode, board: &str) -> (GameState, Vec<u16>) {
+        let map = Map::parse(board).unwrap();
+        let mut state = GameState::new(GameConfig {
+            mode,
+            width: map.width,
+            height: map.height,
+            tag_back_immunity_ms: 1000,
+            ..GameConfig::default()
+        })
+        .unwrap();
+        let spawns = map.spawns.clone();
+        state.map = map;
+        for _ in &spawns {
+            state.gen_player().unwrap();
+        }
+        let ids: Vec<_> = spawns
+            .iter()
+            .map(|&(x, y)| state.grid.at(x, y).unwrap())
+            .collect();
+        for player in &mut state.players {
+            player.set_it(player.id == ids[0]);
+        }
+        (state, ids)
+    }
+
+    fn is_it(state: &GameState, id: u16) -> bool {
+        state.get_player(id).unwrap().is_it
+    }
+
+    fn is_frozen(state: &GameState, id: u16) -> bool {
+        state.get_player(id).unwrap().frozen
+    }
+
+    /// The arena with three players on it, saved as JSON.
+    fn saved_arena() -> serde_json::Value {
+        let mut state = arena();
+        for _ in 0..3 {
+            state.gen_player().unwrap();
+        }
+        serde_json::to_value(&state).unwrap()
+    }
+
+    fn restore(saved: serde_json::Value) -> Result<GameState, String> {
+        serde_json::from_value(saved).map_err(|e| e.to_string())
+    }
+
+    /// A game played in timed rounds that start once `min_players` join.
+    fn timed(min_players: usize) -> GameState {
+        GameState::new(GameConfig {
+            round_secs: Some(60),
+            min_players,
+            ..GameConfig::default()
+        })
+        .unwrap()
+    }
+
+    /// Skip to the end of whatever phase the round is in.
+    fn time_up(state: &mut GameState) {
+        state.round.until = Some(Instant::now());
+        state.advance_round();
+    }
+
+    #[test]
+    fn players_start_on_spawn_points_while_there_are_any() {
+        let mut state = arena();
+        for _ in 0..state.map.spawns.len() {
+            let player = state.gen_player().unwrap();
+            assert!(state.map.spawns.contains(&(player.x, player.y)));
+        }
+    }
+
+    #[test]
+    fn players_start_on_free_floor_once_the_spawn_points_are_taken() {
+        let mut state = arena();
+        let floor = state.map.floor_tiles();
+        for _ in 0..floor {
+            let player = state.gen_player().unwrap();
+            assert!(!state.map.blocked(player.x, player.y));
+        }
+        let taken: HashSet<_> = state.players.iter().map(|p| (p.x, p.y)).collect();
+        assert_eq!(taken.len(), floor);
+
+        let full = state.gen_player().unwrap_err();
+        assert!(matches!(full.downcast_ref(), Some(GameError::BoardFull)));
+    }
+
+    #[test]
+    fn only_the_players_own_token_is_accepted() {
+        let mut state = arena();
+        let player = state.gen_player().unwrap();
+        let other = state.gen_player().unwrap();
+        assert!(state.authorize(player.id, Some(&player.token)).unwrap());
+        assert!(!state.authorize(player.id, Some(&other.token)).unwrap());
+        assert!(!state
+            .authorize(player.id, Some(&player.token[..8]))
+            .unwrap());
+        assert!(!state.authorize(player.id, None).unwrap());
+    }
+
+    #[test]
+    fn moves_waiting_on_a_tick_are_turned_down_by_a_pause() {
+        let mut state = GameState::new(GameConfig {
+            tick_ms: Some(100),
+            ..GameConfig::default()
+        })
+        .unwrap();
+        let mover = state.gen_player().unwrap();
+        let looker = state.gen_player().unwrap();
+        state.teleport(mover.id, 5, 5).unwrap();
+        let Submitted::Queued(mut moved) = state.submit(mover.id, MoveDir::Up).unwrap() else {
+            panic!("the move should wait for the tick");
+        };
+        let Submitted::Queued(mut looked) = state.submit(looker.id, MoveDir::None).unwrap() else {
+            panic!("the look should wait for the tick");
+        };
+
+        state.pause();
+        state.resolve_tick();
+        assert!(matches!(moved.try_recv(), Ok(Err(GameError::Paused))));
+        assert!(matches!(looked.try_recv(), Ok(Ok(()))));
+        let mover = state.get_player(mover.id).unwrap();
+        assert_eq!((mover.x, mover.y), (5, 5));
+    }
+
+    #[test]
+    fn immunity_isnt_used_up_while_paused() {
+        let immunity = Duration::from_millis(200);
+        let mut state = GameState::new(GameConfig {
+            spawn_immunity_ms: immunity.as_millis() as u64,
+            ..GameConfig::default()
+        })
+        .unwrap();
+        let early = state.gen_player().unwrap();
+        std::thread::sleep(immunity + Duration::from_millis(50));
+        let late = state.gen_player().unwrap();
+
+        state.pause();
+        std::thread::sleep(immunity + Duration::from_millis(50));
+        state.resume();
+        // the early player's immunity ran out before the pause and stays out
+        assert!(!state.get_player(early.id).unwrap().immune());
+        assert!(state.get_player(late.id).unwrap().immune());
+    }
+
+    #[test]
+    fn timed_rounds_go_through_every_phase() {
+        let mut state = timed(2);
+        state.gen_player().unwrap();
+        state.advance_round();
+        assert_eq!(state.round.phase, RoundPhase::Lobby);
+
+        state.gen_player().unwrap();
+        state.advance_round();
+        assert_eq!(state.round.phase, RoundPhase::Warmup);
+        assert_eq!(state.round.number, 1);
+        assert_eq!(state.players.iter().filter(|p| p.is_it).count(), 1);
+
+        time_up(&mut state);
+        assert_eq!(state.round.phase, RoundPhase::Playing);
+
+        time_up(&mut state);
+        assert_eq!(state.round.phase, RoundPhase::Over);
+        assert!(state.players.iter().all(|p| !p.is_it));
+        assert_eq!(state.history().len(), 1);
+        assert!(state.history()[0].winner.is_some());
+
+        time_up(&mut state);
+        assert_eq!(state.round.phase, RoundPhase::Warmup);
+        assert_eq!(state.round.number, 2);
+    }
+
+    #[test]
+    fn rounds_wait_in_the_lobby_without_enough_players() {
+        let mut state = timed(2);
+        let first = state.gen_player().unwrap();
+        state.gen_player().unwrap();
+        state.advance_round();
+        state.remove_player(first.id).unwrap();
+        state.advance_round();
+        assert_eq!(state.round.phase, RoundPhase::Lobby);
+
+        let first = state.gen_player().unwrap();
+        state.advance_round();
+        time_up(&mut state);
+        time_up(&mut state);
+        assert_eq!(state.round.phase, RoundPhase::Over);
+        state.remove_player(first.id).unwrap();
+        time_up(&mut state);
+        assert_eq!(state.round.phase, RoundPhase::Lobby);
+    }
+
+    #[test]
+    fn a_round_that_loses_its_players_is_called_off() {
+        let mut state = timed(2);
+        let stays = state.gen_player().unwrap();
+        let leaves = state.gen_player().unwrap();
+        state.advance_round();
+        time_up(&mut state);
+        assert_eq!(state.round.phase, RoundPhase::Playing);
+
+        state.remove_player(leaves.id).unwrap();
+        state.advance_round();
+        assert_eq!(state.round.phase, RoundPhase::Lobby);
+        assert!(state.history().is_empty());
+        let stays = state.get_player(stays.id).unwrap();
+        let card = stays.score.card(stays.id, &stays.name, stays.is_it);
+        assert_eq!(card.rounds_won, 0);
+        assert!(!stays.is_it);
+    }
+
+    #[test]
+    fn saved_games_restore_as_they_were() {
+        let mut state = arena();
+        let players: Vec<_> = (0..3).map(|_| state.gen_player().unwrap()).collect();
+        state.record_request();
+        let saved = serde_json::to_string(&state).unwrap();
+
+        let mut restored: GameState = serde_json::from_str(&saved).unwrap();
+        assert_eq!((restored.map.width, restored.map.height), (25, 10));
+        assert_eq!(restored.map.walls, state.map.walls);
+        assert_eq!(restored.stats.requests, 1);
+        for player in &players {
+            let again = restored.get_player(player.id).unwrap();
+            assert_eq!(again.name, player.name);
+            assert_eq!(again.token, player.token);
+            assert_eq!(
+                (again.x, again.y, again.is_it),
+                (player.x, player.y, player.is_it)
+            );
+            assert_eq!(restored.grid.at(player.x, player.y), Some(player.id));
+            // the id is taken, so it can't be handed out to anyone new
+            assert!(!restored.ids.claim(player.id));
+        }
+        assert_eq!(serde_json::to_string(&restored).unwrap(), saved);
+    }
+
+    #[test]
+    fn players_cant_be_restored_into_a_wall() {
+        let mut saved = saved_arena();
+        let wall = arena().map.walls[0];
+        saved["players"][0]["x"] = wall.x.into();
+        saved["players"][0]["y"] = wall.y.into();
+        let err = restore(saved).unwrap_err();
+        assert!(err.contains("stuck in a wall"), "{err}");
+    }
+
+    #[test]
+    fn players_cant_be_restored_on_top_of_each_other() {
+        let mut saved = saved_arena();
+        saved["players"][1]["x"] = saved["players"][0]["x"].clone();
+        saved["players"][1]["y"] = saved["players"][0]["y"].clone();
+        let err = restore(saved).unwrap_err();
+        assert!(err.contains("are both at"), "{err}");
+    }
+
+    #[test]
+    fn players_cant_be_restored_with_the_same_id() {
+        let mut saved = saved_arena();
+        saved["players"][1]["id"] = saved["players"][0]["id"].clone();
+        let err = restore(saved).unwrap_err();
+        assert!(err.contains("more than one player"), "{err}");
+    }
+
+    #[test]
+    fn classic_tags_pass_it_on_but_cant_be_returned_straight_away() {
+        let (mut state, ids) = on_board(GameMode::Classic, "SS.");
+        let (it, runner) = (ids[0], ids[1]);
+        state.move_player(it, &MoveDir::Right).unwrap();
+        assert!(!is_it(&state, it));
+        assert!(is_it(&state, runner));
+
+        // whoever tagged is immune for a moment
+        state.move_player(runner, &MoveDir::Left).unwrap();
+        assert!(!is_it(&state, it));
+        assert!(is_it(&state, runner));
+        assert_eq!(state.tags(), 1);
+    }
+
+    #[test]
+    fn infection_spreads_until_the_last_runner_is_caught() {
+        let (mut state, ids) = on_board(GameMode::Infection, "SS.S");
+        let (first, second, last) = (ids[0], ids[1], ids[2]);
+        state.move_player(first, &MoveDir::Right).unwrap();
+        assert!(is_it(&state, first));
+        assert!(is_it(&state, second));
+        assert!(state.history().is_empty());
+
+        state.move_player(second, &MoveDir::Right).unwrap();
+        state.move_player(second, &MoveDir::Right).unwrap();
+        // the last one caught wins, and someone starts the next round as it
+        assert_eq!(state.history().len(), 1);
+        assert_eq!(state.history()[0].winner, Some(last));
+        assert_eq!(state.players.iter().filter(|p| p.is_it).count(), 1);
+    }
+
+    #[test]
+    fn freeze_tag_freezes_runners_until_a_teammate_rescues_them() {
+        let (mut state, ids) = on_board(GameMode::Freeze, "SS\nS.");
+        let (it, frozen, rescuer) = (ids[0], ids[1], ids[2]);
+        state.move_player(it, &MoveDir::Right).unwrap();
+        assert!(is_frozen(&state, frozen));
+        assert!(!is_it(&state, frozen));
+
+        // frozen players can't move, and can't be tagged again
+        let stuck = state.move_player(frozen, &MoveDir::Down).unwrap_err();
+        assert!(matches!(stuck.downcast_ref(), Some(GameError::Frozen(_))));
+        state.move_player(it, &MoveDir::Right).unwrap();
+        assert_eq!(state.tags(), 1);
+
+        // up is towards the bottom row, as `delta` has it
+        state.move_player(rescuer, &MoveDir::Right).unwrap();
+        state.move_player(rescuer, &MoveDir::Down).unwrap();
+        assert!(!is_frozen(&state, frozen));
+
+        state.move_player(it, &MoveDir::Right).unwrap();
+        assert!(is_frozen(&state, frozen));
+        assert!(state.history().is_empty());
+
+        // freezing the last runner wins the round for whoever froze them
+        state.move_player(rescuer, &MoveDir::Left).unwrap();
+        state.move_player(it, &MoveDir::Up).unwrap();
+        assert_eq!(state.history().len(), 1);
+        assert_eq!(state.history()[0].winner, Some(it));
+        assert!(state.players.iter().all(|p| !p.frozen));
+    }
+
+    /// Check that the grid has every player on their tile and at their place
+    /// in the list, and nobody else.
+    fn assert_grid_in_step(state: &GameState) {
+        for (idx, player) in state.players.iter().enumerate() {
+            assert_eq!(state.get_player_index(player.id).unwrap(), idx);
+            assert_eq!(state.grid.at(player.x, player.y), Some(player.id));
+        }
+        assert_eq!(state.grid.indexes().len(), state.players.len());
+        let occupied = state.config.width as usize * state.config.height as usize
+            - state.grid.empty(|_, _| false).len();
+        assert_eq!(occupied, state.players.len());
+    }
+
+    #[test]
+    fn players_after_one_that_leaves_can_still_be_found() {
+        let mut state = GameState::default();
+        let players: Vec<_> = (0..5).map(|_| state.gen_player().unwrap()).collect();
+
+        state.remove_player(players[2].id).unwrap();
+        assert!(state.get_player_index(players[2].id).is_err());
+        assert_eq!(state.grid.at(players[2].x, players[2].y), None);
+        assert_grid_in_step(&state);
+
+        state.kick(players[0].id).unwrap();
+        state.remove_player(players[4].id).unwrap();
+        assert_eq!(state.players.len(), 2);
+        assert_grid_in_step(&state);
+    }
+
+    #[test]
+    fn a_line_of_players_moving_together_all_move() {
+        let mut state = GameState::new(GameConfig {
+            tick_ms: Some(100),
+            ..GameConfig::default()
+        })
+        .unwrap();
+        let players: Vec<_> = (0..3).map(|_| state.gen_player().unwrap()).collect();
+        for (x, player) in (5..).zip(&players) {
+            state.teleport(player.id, x, 5).unwrap();
+        }
+        // the back of the line goes first, so every move but the front one
+        // is onto a tile that's only free once the one in front has moved
+        for player in &players {
+            state.submit(player.id, MoveDir::Right).unwrap();
+        }
+        state.resolve_tick();
+
+        for (x, player) in (6..).zip(&players) {
+            let moved = state.get_player(player.id).unwrap();
+            assert_eq!((moved.x, moved.y), (x, 5));
         }
+        assert_eq!(state.grid.at(5, 5), None);
+        assert_grid_in_step(&state);
     }
 }
@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A player that wants to move from one tile to another this tick.
+#[derive(Clone, Copy, Debug)]
+pub struct Intent {
+    pub id: u16,
+    pub from: (i16, i16),
+    pub to: (i16, i16),
+}
+
+/// What happens when every queued move in a tick is made at the same time.
+#[derive(Debug, Default)]
+pub struct Outcome {
+    /// Players that made it to where they wanted to go.
+    pub moves: Vec<(u16, (i16, i16))>,
+    /// Pairs of players, lowest id first, that ran into each other and stayed
+    /// put. These are the tags if one of them is it.
+    pub collisions: Vec<(u16, u16)>,
+}
+
+/// Resolve all moves for a tick simultaneously, so the order requests arrived
+/// in doesn't matter. `standing` is every player that isn't trying to move.
+///
+/// - Two or more players moving onto the same tile all stay put, and each of
+///   them has run into every other, so if one of them is it they can tag any
+///   of the rest just like they could tag someone standing there.
+/// - A player moving onto someone who stays put stays put too.
+/// - Two players moving onto each other's tiles (a head-on swap) both stay put.
+/// - Anyone else moves, including into a tile that's being vacated.
+///
+/// Players that stay put can block others in turn, so this repeats until
+/// nothing changes. Moves onto walls should be left out of `intents`.
+pub fn resolve(standing: &[(u16, (i16, i16))], intents: &[Intent]) -> Outcome {
+    let mut moving: BTreeMap<u16, Intent> = intents.iter().map(|i| (i.id, *i)).collect();
+    let mut still: BTreeMap<(i16, i16), u16> =
+        standing.iter().map(|&(id, pos)| (pos, id)).collect();
+    let mut collisions = BTreeSet::new();
+    let pair = |a: u16, b: u16| (a.min(b), a.max(b));
+
+    loop {
+        let mut stopped = BTreeSet::new();
+        for (&id, intent) in &moving {
+            let contenders: Vec<u16> = moving
+                .values()
+                .filter(|other| other.to == intent.to)
+                .map(|other| other.id)
+                .collect();
+            if contenders.len() > 1 {
+                for &other in contenders.iter().filter(|&&other| other != id) {
+                    collisions.insert(pair(id, other));
+                }
+                stopped.insert(id);
+            } else if let Some(&blocker) = still.get(&intent.to) {
+                collisions.insert(pair(id, blocker));
+                stopped.insert(id);
+            } else if let Some(other) = moving
+                .values()
+                .find(|other| other.from == intent.to && other.to == intent.from)
+            {
+                collisions.insert(pair(id, other.id));
+                stopped.insert(id);
+            }
+        }
+
+        if stopped.is_empty() {
+            break;
+        }
+        for id in stopped {
+            if let Some(intent) = moving.remove(&id) {
+                still.insert(intent.from, id);
+            }
+        }
+    }
+
+    Outcome {
+        moves: moving.values().map(|i| (i.id, i.to)).collect(),
+        collisions: collisions.into_iter().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn intent(id: u16, from: (i16, i16), to: (i16, i16)) -> Intent {
+        Intent { id, from, to }
+    }
+
+    #[test]
+    fn head_on_swap_stays_put() {
+        let outcome = resolve(&[], &[intent(1, (0, 0), (1, 0)), intent(2, (1, 0), (0, 0))]);
+        assert!(outcome.moves.is_empty());
+        assert_eq!(outcome.collisions, [(1, 2)]);
+    }
+
+    #[test]
+    fn contenders_for_a_tile_all_stay_put_and_run_into_each_other() {
+        let outcome = resolve(
+            &[],
+            &[
+                intent(3, (0, 1), (1, 1)),
+                intent(1, (1, 0), (1, 1)),
+                intent(2, (2, 1), (1, 1)),
+            ],
+        );
+        assert!(outcome.moves.is_empty());
+        assert_eq!(outcome.collisions, [(1, 2), (1, 3), (2, 3)]);
+    }
+
+    #[test]
+    fn moving_onto_someone_standing_still_stays_put() {
+        let outcome = resolve(&[(2, (1, 0))], &[intent(1, (0, 0), (1, 0))]);
+        assert!(outcome.moves.is_empty());
+        assert_eq!(outcome.collisions, [(1, 2)]);
+    }
+
+    #[test]
+    fn chain_moves_into_vacated_tiles() {
+        // 1 follows 2 follows 3, all to the right
+        let outcome = resolve(
+            &[],
+            &[
+                intent(1, (0, 0), (1, 0)),
+                intent(2, (1, 0), (2, 0)),
+                intent(3, (2, 0), (3, 0)),
+            ],
+        );
+        assert_eq!(outcome.moves, [(1, (1, 0)), (2, (2, 0)), (3, (3, 0))]);
+        assert!(outcome.collisions.is_empty());
+    }
+
+    #[test]
+    fn blocked_chain_stops_everyone_behind() {
+        // 3 walks into 4, who isn't moving, so 2 and then 1 are stuck too
+        let outcome = resolve(
+            &[(4, (3, 0))],
+            &[
+                intent(1, (0, 0), (1, 0)),
+                intent(2, (1, 0), (2, 0)),
+                intent(3, (2, 0), (3, 0)),
+            ],
+        );
+        assert!(outcome.moves.is_empty());
+        assert_eq!(outcome.collisions, [(1, 2), (2, 3), (3, 4)]);
+    }
+
+    #[test]
+    fn rotation_all_moves() {
+        let outcome = resolve(
+            &[],
+            &[
+                intent(1, (0, 0), (1, 0)),
+                intent(2, (1, 0), (1, 1)),
+                intent(3, (1, 1), (0, 1)),
+                intent(4, (0, 1), (0, 0)),
+            ],
+        );
+        assert_eq!(outcome.moves.len(), 4);
+        assert!(outcome.collisions.is_empty());
+    }
+}
@@ -2,14 +2,16 @@ use ratatui::{
     style::{Color, Style},
     symbols::Marker,
     widgets::{
-        canvas::{Canvas, Context},
+        canvas::{Canvas, Context, Shape},
         Block, Borders,
     },
 };
 
-use crate::actor::Game;
-
-pub fn render(state: &Game, width: u16, height: u16) -> Canvas<impl Fn(&mut Context<'_>) + '_> {
+pub fn render<S: Shape>(
+    state: &S,
+    width: u16,
+    height: u16,
+) -> Canvas<'_, impl Fn(&mut Context<'_>) + '_> {
     Canvas::default()
         .x_bounds([0f64, width.into()])
         .y_bounds([0f64, height.into()])
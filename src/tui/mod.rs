@@ -1,4 +1,6 @@
 mod cli;
 mod game;
+mod replay;
 
 pub use cli::{run, setup_panic_hook};
+pub use replay::replay;
@@ -0,0 +1,271 @@
+use anyhow::anyhow;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind};
+use ratatui::prelude::*;
+use ratatui::widgets::canvas::{Painter, Shape};
+use ratatui::widgets::{Gauge, Paragraph, Wrap};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::server::{Board, EventRecord, GameEvent};
+
+use super::cli::{restore_terminal, setup_terminal};
+use super::game;
+
+/// Long stretches where nothing happened are sped through.
+const MAX_GAP: Duration = Duration::from_secs(2);
+const SPEEDS: [f64; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+/// The board is kept every this many events, and any other board is rebuilt
+/// by playing forward from the last one kept before it.
+const KEYFRAME_EVERY: usize = 100;
+
+/// An event in the log and when it happened.
+struct Step {
+    time_ms: u64,
+    event: GameEvent,
+}
+
+struct Replay {
+    room: String,
+    frames: Vec<Step>,
+    /// The board after every `KEYFRAME_EVERY`th frame, starting with the first.
+    keyframes: Vec<Board>,
+    /// The board after the current frame.
+    board: Board,
+    pos: usize,
+    playing: bool,
+    speed: usize,
+    /// When the current frame was shown, to know when the next one is due.
+    shown_at: Instant,
+}
+
+impl Shape for Board {
+    fn draw(&self, painter: &mut Painter) {
+        for wall in &self.walls {
+            if let Some((x, y)) = painter.get_point(wall.x.into(), wall.y.into()) {
+                painter.paint(x, y, Color::DarkGray);
+            }
+        }
+        for player in &self.players {
+            if let Some((x, y)) = painter.get_point(player.x.into(), player.y.into()) {
+                let color = if player.is_it {
+                    Color::Red
+                } else if player.frozen {
+                    Color::LightBlue
+                } else {
+                    Color::Cyan
+                };
+                painter.paint(x, y, color);
+            }
+        }
+    }
+}
+
+impl Replay {
+    /// Rebuild every board state for `room` from an event log, or for the
+    /// first room in the log if none is given.
+    fn load(path: &Path, room: Option<&str>) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("could not read event log {}: {e}", path.display()))?;
+        let mut room = room.map(String::from);
+        let mut board = Board::default();
+        let mut frames = Vec::new();
+        let mut keyframes = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: EventRecord = serde_json::from_str(line)
+                .map_err(|e| anyhow!("line {} of {}: {e}", n + 1, path.display()))?;
+            let room = room.get_or_insert_with(|| record.room.clone());
+            // looks don't change anything worth watching
+            if record.room != *room || matches!(record.event, GameEvent::Look { .. }) {
+                continue;
+            }
+            board.apply(&record.event);
+            if frames.len() % KEYFRAME_EVERY == 0 {
+                keyframes.push(board.clone());
+            }
+            frames.push(Step {
+                time_ms: record.time_ms,
+                event: record.event,
+            });
+        }
+
+        let Some(room) = room.filter(|_| !frames.is_empty()) else {
+            return Err(anyhow!("no events to replay in {}", path.display()));
+        };
+        Ok(Self {
+            room,
+            frames,
+            board: keyframes[0].clone(),
+            keyframes,
+            pos: 0,
+            playing: true,
+            speed: 2,
+            shown_at: Instant::now(),
+        })
+    }
+
+    fn frame(&self) -> &Step {
+        &self.frames[self.pos]
+    }
+
+    /// Move to the next frame once it's due.
+    fn on_tick(&mut self) {
+        let Some(next) = self.frames.get(self.pos + 1) else {
+            self.playing = false;
+            return;
+        };
+        let gap = Duration::from_millis(next.time_ms.saturating_sub(self.frame().time_ms));
+        let due = gap.min(MAX_GAP).div_f64(SPEEDS[self.speed]);
+        if self.playing && self.shown_at.elapsed() >= due {
+            self.seek(self.pos + 1);
+        }
+    }
+
+    fn seek(&mut self, pos: usize) {
+        let pos = pos.min(self.frames.len() - 1);
+        let keyframe = pos / KEYFRAME_EVERY * KEYFRAME_EVERY;
+        // carry on from the current board unless the keyframe is closer
+        let from = if (keyframe..=pos).contains(&self.pos) {
+            self.pos
+        } else {
+            self.board = self.keyframes[pos / KEYFRAME_EVERY].clone();
+            keyframe
+        };
+        for step in &self.frames[from + 1..=pos] {
+            self.board.apply(&step.event);
+        }
+        self.pos = pos;
+        self.shown_at = Instant::now();
+    }
+
+    /// Handle a key press, returning an error to quit.
+    fn handle_input(&mut self, key: KeyEvent) -> anyhow::Result<()> {
+        let jump = (self.frames.len() / 10).max(1);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Err(anyhow!("Quitting")),
+            KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => {
+                return Err(anyhow!("Quitting"))
+            }
+            KeyCode::Char(' ') => {
+                self.playing = !self.playing;
+                self.shown_at = Instant::now();
+            }
+            KeyCode::Right | KeyCode::Char('l') => {
+                self.playing = false;
+                self.seek(self.pos + 1);
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                self.playing = false;
+                self.seek(self.pos.saturating_sub(1));
+            }
+            KeyCode::PageDown => self.seek(self.pos + jump),
+            KeyCode::PageUp => self.seek(self.pos.saturating_sub(jump)),
+            KeyCode::Home => self.seek(0),
+            KeyCode::End => self.seek(self.frames.len()),
+            KeyCode::Char('+') | KeyCode::Char('=') => {
+                self.speed = (self.speed + 1).min(SPEEDS.len() - 1)
+            }
+            KeyCode::Char('-') => self.speed = self.speed.saturating_sub(1),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Jump to the point on the timeline that was clicked.
+    fn scrub(&mut self, column: u16, timeline: Rect) {
+        let offset = column.saturating_sub(timeline.x);
+        let ratio = f64::from(offset) / f64::from(timeline.width.max(2) - 1);
+        let last = self.frames.len() - 1;
+        self.seek((ratio * last as f64).round() as usize);
+    }
+
+    fn timeline_label(&self) -> String {
+        let start = self.frames[0].time_ms;
+        let end = self.frames[self.frames.len() - 1].time_ms;
+        let state = if self.playing { "playing" } else { "paused" };
+        format!(
+            "{} / {}  {state} at {}x",
+            clock(self.frame().time_ms.saturating_sub(start)),
+            clock(end.saturating_sub(start)),
+            SPEEDS[self.speed],
+        )
+    }
+}
+
+/// Watch a game recorded in an event log, see the README for the controls.
+pub fn replay(path: &Path, room: Option<&str>) -> anyhow::Result<()> {
+    let mut replay = Replay::load(path, room)?;
+    let mut terminal = setup_terminal()?;
+    loop {
+        let mut timeline = Rect::default();
+        let drawn = terminal.draw(|rect| timeline = render(&replay, rect));
+        if drawn.is_err() {
+            break;
+        }
+
+        if event::poll(Duration::from_millis(20))? {
+            match event::read()? {
+                Event::Key(key) if replay.handle_input(key).is_err() => break,
+                Event::Mouse(mouse) if mouse.row == timeline.y && timeline.width > 0 => {
+                    if let MouseEventKind::Down(MouseButton::Left)
+                    | MouseEventKind::Drag(MouseButton::Left) = mouse.kind
+                    {
+                        replay.scrub(mouse.column, timeline);
+                    }
+                }
+                _ => {}
+            }
+        }
+        replay.on_tick();
+    }
+
+    terminal.show_cursor()?;
+    restore_terminal()?;
+    Ok(())
+}
+
+/// Draw the board, timeline and controls, returning where the timeline is.
+fn render(replay: &Replay, rect: &mut Frame<'_>) -> Rect {
+    let size = rect.size();
+    let board = &replay.board;
+    let w: u16 = board.width.unsigned_abs();
+    let h: u16 = board.height.unsigned_abs();
+    // the board needs at least a tile inside its border, then the timeline
+    // and controls go underneath
+    if size.width < w.max(3) || size.height < h.max(3) + 4 {
+        rect.render_widget(Paragraph::new("make the terminal bigger to watch"), size);
+        return Rect::default();
+    }
+    rect.render_widget(game::render(board, w, h), Rect::new(0, 0, w, h));
+
+    let timeline = Rect::new(0, h + 1, size.width, 1);
+    let ratio = replay.pos as f64 / (replay.frames.len() - 1).max(1) as f64;
+    rect.render_widget(
+        Gauge::default()
+            .gauge_style(Style::default().fg(Color::Cyan))
+            .ratio(ratio)
+            .label(replay.timeline_label()),
+        timeline,
+    );
+
+    let text = format!(
+        "{} event {}/{}: {:?}\n\nspace play/pause  left/right step  pgup/pgdn jump  \
+         home/end  +/- speed  click the timeline to seek  q quit",
+        replay.room,
+        replay.pos + 1,
+        replay.frames.len(),
+        replay.frame().event,
+    );
+    rect.render_widget(
+        Paragraph::new(text).wrap(Wrap { trim: true }),
+        Rect::new(0, h + 3, size.width, size.height - h - 3),
+    );
+    timeline
+}
+
+fn clock(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}